@@ -0,0 +1,566 @@
+use std::io::{Cursor, Seek, Write};
+
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::builder::package_document::Ids;
+use crate::error::{Error, Result};
+use crate::parser::{Href, Identifier, ManifestItem, Metadata, Spine, Toc, TocNode};
+use crate::util::{media_type, path, xml_util};
+
+pub(crate) mod nav;
+pub(crate) mod package_document;
+
+const MIMETYPE: &str = "application/epub+zip";
+const CONTAINER_PATH: &str = "META-INF/container.xml";
+
+/// Writes an EPUB 3 archive from the model `Parser::meta` returns.
+///
+/// Paths, including those of the table of contents, are archive paths as reported by
+/// `Parser`. The package document goes to `EPUB/content.opf` unless
+/// [`EpubBuilder::package_path`] says otherwise. A navigation document is generated from the
+/// table of contents unless a resource has the `nav` property.
+///
+/// ```no_run
+/// use ezpub::builder::EpubBuilder;
+/// use ezpub::parser::{ManifestItem, Metadata, Spine, SpineItem, Title, Toc};
+///
+/// let metadata = Metadata {
+///     titles: vec![Title {
+///         value: String::from("Sample Book"),
+///         ..Default::default()
+///     }],
+///     languages: vec![String::from("en")],
+///     ..Default::default()
+/// };
+/// let chapter = ManifestItem {
+///     id: String::from("chapter-1"),
+///     href: String::from("chapter-1.xhtml"),
+///     ..Default::default()
+/// };
+/// let spine = Spine {
+///     items: vec![SpineItem {
+///         idref: String::from("chapter-1"),
+///         linear: true,
+///         ..Default::default()
+///     }],
+///     ..Default::default()
+/// };
+///
+/// let bytes = EpubBuilder::new(metadata)
+///     .resource(chapter, std::fs::read("chapter-1.xhtml").unwrap())
+///     .spine(spine)
+///     .toc(Toc::default())
+///     .ncx(true)
+///     .to_bytes()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct EpubBuilder {
+    metadata: Metadata,
+    package_path: String,
+    resources: Vec<(ManifestItem, Vec<u8>)>,
+    spine: Spine,
+    toc: Toc,
+    ncx: bool,
+}
+
+impl EpubBuilder {
+    pub fn new(metadata: Metadata) -> EpubBuilder {
+        EpubBuilder {
+            metadata,
+            package_path: String::from("EPUB/content.opf"),
+            resources: vec![],
+            spine: Spine::default(),
+            toc: Toc::default(),
+            ncx: false,
+        }
+    }
+
+    /// Archive path of the package document, `EPUB/content.opf` by default.
+    pub fn package_path(mut self, path: &str) -> EpubBuilder {
+        self.package_path = path.trim_start_matches('/').to_string();
+        self
+    }
+
+    /// Add a resource to the manifest. `item.path` is its archive path; when empty it is
+    /// resolved from `item.href` relative to the package document. A missing media type is
+    /// guessed from the file extension.
+    pub fn resource(mut self, item: ManifestItem, data: impl Into<Vec<u8>>) -> EpubBuilder {
+        self.resources.push((item, data.into()));
+        self
+    }
+
+    /// Reading order. Items are matched to the manifest by `idref`, or by `path` when `idref`
+    /// is empty.
+    pub fn spine(mut self, spine: Spine) -> EpubBuilder {
+        self.spine = spine;
+        self
+    }
+
+    pub fn toc(mut self, toc: Toc) -> EpubBuilder {
+        self.toc = toc;
+        self
+    }
+
+    /// Also write an NCX for EPUB 2 reading systems.
+    pub fn ncx(mut self, ncx: bool) -> EpubBuilder {
+        self.ncx = ncx;
+        self
+    }
+
+    /// Write the archive to `writer`: `mimetype` first and uncompressed, then
+    /// `container.xml`, the package document, the navigation documents and the resources.
+    ///
+    /// Fails when a spine item or the spine `toc` matches no resource, or when a manifest id or
+    /// resource path is given twice or taken by a generated navigation document or NCX.
+    pub fn write<W: Write + Seek>(&self, writer: W) -> Result<W> {
+        let mut metadata = self.metadata.clone();
+        let unique_identifier = unique_identifier(&mut metadata);
+        if metadata.modified.is_none() {
+            metadata.modified = Some(package_document::timestamp());
+        }
+
+        let mut manifest: Vec<ManifestItem> = self
+            .resources
+            .iter()
+            .map(|(item, _)| self.manifest_item(item))
+            .collect();
+        for (i, item) in manifest.iter().enumerate() {
+            if manifest[..i].iter().any(|m| m.id == item.id) {
+                return Err(Error::DuplicateManifestItem {
+                    id: item.id.clone(),
+                });
+            }
+            if manifest[..i].iter().any(|m| m.path == item.path) {
+                return Err(Error::DuplicateResource {
+                    path: item.path.clone(),
+                });
+            }
+        }
+
+        let mut spine = self.spine.clone();
+        for item in &mut spine.items {
+            if item.idref.is_empty() {
+                let manifest_item =
+                    manifest
+                        .iter()
+                        .find(|m| m.path == item.path)
+                        .ok_or_else(|| Error::MissingResource {
+                            path: item.path.clone(),
+                        })?;
+                item.idref = manifest_item.id.clone();
+            } else if !manifest.iter().any(|m| m.id == item.idref) {
+                return Err(Error::MissingManifestItem {
+                    id: item.idref.clone(),
+                });
+            }
+        }
+        // the NCX is either generated or one of the resources
+        match &spine.toc {
+            Some(toc) if !self.ncx && !manifest.iter().any(|m| &m.id == toc) => {
+                return Err(Error::MissingManifestItem { id: toc.clone() });
+            }
+            _ => {}
+        }
+
+        let mut toc = self.toc.clone();
+        if !toc.contents.iter().any(nav::is_listed) {
+            // navigation documents and NCXs need at least one entry, link the first page
+            let first_page = spine
+                .items
+                .first()
+                .and_then(|item| manifest.iter().find(|m| m.id == item.idref));
+            if let Some(first_page) = first_page {
+                toc.contents.push(TocNode {
+                    title: metadata.title().unwrap_or_default().to_string(),
+                    href: Some(Href {
+                        path: first_page.path.clone(),
+                        fragment: None,
+                    }),
+                    ..Default::default()
+                });
+            }
+        }
+
+        let mut generated = vec![];
+        if !manifest
+            .iter()
+            .any(|item| item.properties.iter().any(|p| p == "nav"))
+        {
+            let item = self.generated_item(&manifest, "nav", "nav.xhtml", &["nav"]);
+            let doc = nav::nav_doc(&toc, &metadata, &item.path);
+            manifest.push(item.clone());
+            generated.push((item, doc));
+        }
+        if self.ncx {
+            let item = self.generated_item(&manifest, "ncx", "toc.ncx", &[]);
+            let doc = nav::ncx(&toc, &metadata, &unique_identifier, &item.path);
+            spine.toc = Some(item.id.clone());
+            manifest.push(item.clone());
+            generated.push((item, doc));
+        }
+
+        // a resource can't take the place of a generated navigation document or NCX
+        let given = &manifest[..self.resources.len()];
+        for (item, _) in &generated {
+            if given.iter().any(|m| m.path == item.path) {
+                return Err(Error::DuplicateResource {
+                    path: item.path.clone(),
+                });
+            }
+        }
+
+        let mut ids = Ids::new(&metadata).excluding(manifest.iter().map(|m| m.id.as_str()));
+        if let Some(identifier) = metadata
+            .identifiers
+            .iter_mut()
+            .find(|i| i.value == unique_identifier)
+        {
+            identifier.id.get_or_insert_with(|| ids.next("uid"));
+        }
+
+        let pkg_doc = package_document::write(&metadata, &manifest, &spine);
+
+        let mut zip = ZipWriter::new(writer);
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        zip.start_file("mimetype", stored).map_err(Error::Archive)?;
+        zip.write_all(MIMETYPE.as_bytes())?;
+        zip.start_file(CONTAINER_PATH, deflated)
+            .map_err(Error::Archive)?;
+        zip.write_all(container(&self.package_path).as_bytes())?;
+        zip.start_file(self.package_path.as_str(), deflated)
+            .map_err(Error::Archive)?;
+        zip.write_all(pkg_doc.as_bytes())?;
+
+        let resources = generated
+            .iter()
+            .map(|(item, doc)| (item, doc.as_bytes()))
+            .chain(
+                manifest
+                    .iter()
+                    .zip(&self.resources)
+                    .map(|(item, (_, data))| (item, data.as_slice())),
+            );
+        for (item, data) in resources {
+            zip.start_file(item.path.as_str(), deflated)
+                .map_err(Error::Archive)?;
+            zip.write_all(data)?;
+        }
+
+        zip.finish().map_err(Error::Archive)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(self.write(Cursor::new(Vec::new()))?.into_inner())
+    }
+
+    fn manifest_item(&self, item: &ManifestItem) -> ManifestItem {
+        let mut item = item.clone();
+        if item.path.is_empty() {
+            item.path = path::resolve(&self.package_path, &item.href);
+        }
+        item.href = path::relative(&self.package_path, &item.path);
+        if item.media_type.is_none() {
+            item.media_type = media_type::guess(&item.path);
+        }
+
+        item
+    }
+
+    /// manifest item for a generated document, next to the package document
+    fn generated_item(
+        &self,
+        manifest: &[ManifestItem],
+        id: &str,
+        href: &str,
+        properties: &[&str],
+    ) -> ManifestItem {
+        let taken = |id: &str| manifest.iter().any(|item| item.id == id);
+        let id = (1..)
+            .map(|n| match n {
+                1 => id.to_string(),
+                n => format!("{}-{}", id, n),
+            })
+            .find(|id| !taken(id))
+            .unwrap_or_default();
+
+        self.manifest_item(&ManifestItem {
+            id,
+            href: href.to_string(),
+            properties: properties.iter().map(|str| str.to_string()).collect(),
+            ..Default::default()
+        })
+    }
+}
+
+/// Value of the unique identifier, generating a UUID when the book has no identifier.
+fn unique_identifier(metadata: &mut Metadata) -> String {
+    if metadata.identifiers.is_empty() {
+        let uuid = format!("urn:uuid:{}", package_document::uuid());
+        metadata.identifiers.push(Identifier {
+            value: uuid.clone(),
+            scheme: Some(String::from("UUID")),
+            ..Default::default()
+        });
+        metadata.unique_identifier = Some(uuid);
+    }
+
+    let value = metadata
+        .unique_identifier
+        .clone()
+        .filter(|value| metadata.identifiers.iter().any(|i| &i.value == value))
+        .unwrap_or_else(|| metadata.identifiers[0].value.clone());
+    metadata.unique_identifier = Some(value.clone());
+
+    value
+}
+
+fn container(package_path: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<container xmlns="urn:oasis:names:tc:opendocument:xmlns:container" version="1.0">
+    <rootfiles>
+        <rootfile full-path="{}" media-type="application/oebps-package+xml"/>
+    </rootfiles>
+</container>
+"#,
+        xml_util::escape(package_path)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use zip::ZipArchive;
+
+    use super::*;
+    use crate::parser::{Creator, Parser, SpineItem, Title};
+    use crate::util::test_util::CHAPTER;
+
+    #[test]
+    fn build_and_parse_back() {
+        let metadata = Metadata {
+            identifiers: [
+                ("urn:isbn:9780000000000", None),
+                ("9780141441146", Some("ISBN")),
+                ("4000123", Some("01")),
+                ("B-1234", Some("publisher-id")),
+            ]
+            .iter()
+            .map(|(value, scheme)| Identifier {
+                value: value.to_string(),
+                scheme: scheme.map(String::from),
+                ..Default::default()
+            })
+            .collect(),
+            titles: vec![Title {
+                value: String::from("Sample & Book"),
+                title_type: Some(String::from("main")),
+                ..Default::default()
+            }],
+            languages: vec![String::from("en")],
+            creators: vec![Creator {
+                name: String::from("Charlotte Brontë"),
+                role: Some(String::from("aut")),
+                file_as: Some(String::from("Brontë, Charlotte")),
+                ..Default::default()
+            }],
+            modified: Some(String::from("2024-01-01T00:00:00Z")),
+            ..Default::default()
+        };
+        let chapter = |id: &str, href: &str| ManifestItem {
+            id: id.to_string(),
+            href: href.to_string(),
+            ..Default::default()
+        };
+        let spine = Spine {
+            items: ["chapter-1", "chapter-2"]
+                .iter()
+                .map(|id| SpineItem {
+                    idref: id.to_string(),
+                    linear: true,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let toc = Toc {
+            contents: vec![TocNode {
+                title: String::from("Part 1"),
+                children: Some(vec![TocNode {
+                    title: String::from("Chapter 1"),
+                    href: Some(Href {
+                        path: String::from("EPUB/text/chapter 1.xhtml"),
+                        fragment: Some(String::from("start")),
+                    }),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let bytes = EpubBuilder::new(metadata)
+            .resource(chapter("chapter-1", "text/chapter%201.xhtml"), CHAPTER)
+            .resource(chapter("chapter-2", "text/chapter-2.xhtml"), CHAPTER)
+            .spine(spine)
+            .toc(toc.clone())
+            .ncx(true)
+            .to_bytes()
+            .unwrap();
+
+        let mut archive = ZipArchive::new(Cursor::new(bytes.as_slice())).unwrap();
+        let mimetype = archive.by_index(0).unwrap();
+        assert_eq!("mimetype", mimetype.name());
+        assert_eq!(CompressionMethod::Stored, mimetype.compression());
+        drop(mimetype);
+
+        let mut parser = Parser::from_bytes(bytes.as_slice()).unwrap();
+        let meta = parser.meta().unwrap();
+        assert_eq!("Sample & Book", meta.title);
+        assert_eq!(
+            Some("Brontë, Charlotte"),
+            meta.metadata.creators[0].file_as.as_deref()
+        );
+        assert_eq!(
+            Some("urn:isbn:9780000000000"),
+            meta.metadata.unique_identifier.as_deref()
+        );
+        assert_eq!(
+            vec![Some("ISBN"), Some("ISBN"), Some("01"), Some("publisher-id")],
+            meta.metadata
+                .identifiers
+                .iter()
+                .map(|identifier| identifier.scheme.as_deref())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["EPUB/text/chapter 1.xhtml", "EPUB/text/chapter-2.xhtml"],
+            meta.spine.paths().collect::<Vec<_>>()
+        );
+        assert_eq!(toc.contents, meta.toc.contents);
+        assert_eq!(
+            Some("EPUB/toc.ncx"),
+            meta.manifest.by_id("ncx").map(|item| item.path.as_str())
+        );
+        assert_eq!(
+            CHAPTER.as_bytes(),
+            parser.resource("EPUB/text/chapter 1.xhtml").unwrap()
+        );
+    }
+
+    #[test]
+    fn reject_unresolved_items() {
+        let chapter = |id: &str, href: &str| ManifestItem {
+            id: id.to_string(),
+            href: href.to_string(),
+            ..Default::default()
+        };
+        let spine_item = |idref: &str, path: &str| Spine {
+            items: vec![SpineItem {
+                idref: idref.to_string(),
+                path: path.to_string(),
+                linear: true,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let builder = || {
+            EpubBuilder::new(Metadata::default())
+                .resource(chapter("chapter-1", "chapter-1.xhtml"), CHAPTER)
+        };
+
+        assert!(builder()
+            .spine(spine_item("", "EPUB/chapter-1.xhtml"))
+            .to_bytes()
+            .is_ok());
+        assert!(matches!(
+            builder().spine(spine_item("", "EPUB/chapter-2.xhtml")).to_bytes(),
+            Err(Error::MissingResource { path }) if path == "EPUB/chapter-2.xhtml"
+        ));
+        assert!(matches!(
+            builder().spine(spine_item("chapter-2", "")).to_bytes(),
+            Err(Error::MissingManifestItem { id }) if id == "chapter-2"
+        ));
+        assert!(matches!(
+            builder().resource(chapter("chapter-1", "chapter-2.xhtml"), CHAPTER).to_bytes(),
+            Err(Error::DuplicateManifestItem { id }) if id == "chapter-1"
+        ));
+        assert!(matches!(
+            builder().resource(chapter("chapter-2", "chapter-1.xhtml"), CHAPTER).to_bytes(),
+            Err(Error::DuplicateResource { path }) if path == "EPUB/chapter-1.xhtml"
+        ));
+        assert!(matches!(
+            builder().resource(chapter("page", "nav.xhtml"), CHAPTER).to_bytes(),
+            Err(Error::DuplicateResource { path }) if path == "EPUB/nav.xhtml"
+        ));
+        assert!(matches!(
+            builder().resource(chapter("ncx-file", "toc.ncx"), "").ncx(true).to_bytes(),
+            Err(Error::DuplicateResource { path }) if path == "EPUB/toc.ncx"
+        ));
+
+        // the spine `toc` needs a generated NCX or a resource with its id
+        let spine = Spine {
+            toc: Some(String::from("ncx")),
+            ..spine_item("chapter-1", "")
+        };
+        assert!(matches!(
+            builder().spine(spine.clone()).to_bytes(),
+            Err(Error::MissingManifestItem { id }) if id == "ncx"
+        ));
+        assert!(builder().spine(spine.clone()).ncx(true).to_bytes().is_ok());
+        assert!(builder()
+            .resource(chapter("ncx", "book.ncx"), "")
+            .spine(spine)
+            .to_bytes()
+            .is_ok());
+    }
+
+    #[test]
+    fn keep_generated_markup_valid() {
+        let chapter = ManifestItem {
+            id: String::from("uid-1"),
+            href: String::from("chapter-1.xhtml"),
+            ..Default::default()
+        };
+        let toc = Toc {
+            contents: vec![
+                TocNode {
+                    title: String::from("Chapter 1"),
+                    href: Some(Href {
+                        path: String::from("EPUB/chapter-1.xhtml"),
+                        fragment: None,
+                    }),
+                    ..Default::default()
+                },
+                TocNode {
+                    title: String::from("Part 2"),
+                    children: Some(vec![TocNode {
+                        title: String::from("Unlinked"),
+                        ..Default::default()
+                    }]),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let bytes = EpubBuilder::new(Metadata::default())
+            .resource(chapter, CHAPTER)
+            .toc(toc)
+            .to_bytes()
+            .unwrap();
+
+        let mut parser = Parser::from_bytes(bytes).unwrap();
+        // the unique identifier's id doesn't clash with the manifest's
+        let pkg_doc = String::from_utf8(parser.raw_resource("EPUB/content.opf").unwrap()).unwrap();
+        assert!(pkg_doc.contains(r#"unique-identifier="uid-2""#));
+        assert!(parser.meta().unwrap().metadata.unique_identifier.is_some());
+        // headings without a linked entry below them are left out
+        let nav_doc = String::from_utf8(parser.raw_resource("EPUB/nav.xhtml").unwrap()).unwrap();
+        assert!(!nav_doc.contains("<span>"));
+        assert!(!nav_doc.contains("Unlinked"));
+        assert_eq!(1, parser.meta().unwrap().toc.contents.len());
+    }
+}
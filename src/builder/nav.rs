@@ -0,0 +1,337 @@
+use std::fmt::Write;
+
+use crate::parser::{Href, Metadata, Toc, TocNode};
+use crate::util::path;
+use crate::util::xml_util::escape;
+
+/// Serialize an EPUB 3 navigation document at `doc_path`, with the table of contents, the
+/// landmarks and the page list.
+pub(crate) fn nav_doc(toc: &Toc, metadata: &Metadata, doc_path: &str) -> String {
+    let title = escape(metadata.title().unwrap_or_default());
+    let lang = metadata
+        .language()
+        .map(|lang| format!(r#" xml:lang="{0}" lang="{0}""#, escape(lang)))
+        .unwrap_or_default();
+
+    let mut xml = String::new();
+    let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(xml, "<!DOCTYPE html>");
+    let _ = writeln!(
+        xml,
+        r#"<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops"{}>"#,
+        lang
+    );
+    let _ = writeln!(xml, "<head>\n    <title>{}</title>\n</head>\n<body>", title);
+
+    let heading = format!("<h1>{}</h1>", title);
+    let navs = [
+        Some(toc_nav(toc, Some(&heading), doc_path, "    ")),
+        landmarks_nav(toc, doc_path, "    "),
+        page_list_nav(toc, doc_path, "    "),
+    ];
+    for nav in navs.into_iter().flatten() {
+        let _ = writeln!(xml, "    {}", nav);
+    }
+
+    xml.push_str("</body>\n</html>\n");
+    xml
+}
+
+/// `nav epub:type="toc"` element, its lines after the first indented by `indent`.
+/// `heading` is inserted verbatim before the list.
+pub(crate) fn toc_nav(toc: &Toc, heading: Option<&str>, doc_path: &str, indent: &str) -> String {
+    let mut xml = String::from(r#"<nav epub:type="toc" id="toc">"#);
+    xml.push('\n');
+    if let Some(heading) = heading {
+        let _ = writeln!(xml, "{}    {}", indent, heading);
+    }
+    write_list(
+        &mut xml,
+        &toc.contents,
+        doc_path,
+        &format!("{}    ", indent),
+    );
+    let _ = write!(xml, "{}</nav>", indent);
+
+    xml
+}
+
+/// `nav epub:type="landmarks"` element, `None` without landmarks
+pub(crate) fn landmarks_nav(toc: &Toc, doc_path: &str, indent: &str) -> Option<String> {
+    let links = toc.landmarks.iter().map(|landmark| {
+        format!(
+            r#"<a epub:type="{}" href="{}">{}</a>"#,
+            escape(&landmark.epub_type),
+            escape(&href(doc_path, &landmark.href)),
+            escape(&landmark.title)
+        )
+    });
+
+    hidden_nav("landmarks", links, indent)
+}
+
+/// `nav epub:type="page-list"` element, `None` without a page list
+pub(crate) fn page_list_nav(toc: &Toc, doc_path: &str, indent: &str) -> Option<String> {
+    let links = toc.page_list.iter().map(|page| {
+        format!(
+            r#"<a href="{}">{}</a>"#,
+            escape(&href(doc_path, &page.href)),
+            escape(&page.label)
+        )
+    });
+
+    hidden_nav("page-list", links, indent)
+}
+
+fn hidden_nav(
+    epub_type: &str,
+    links: impl Iterator<Item = String>,
+    indent: &str,
+) -> Option<String> {
+    let mut xml = format!(r#"<nav epub:type="{0}" id="{0}" hidden="">"#, epub_type);
+    let _ = write!(xml, "\n{}    <ol>\n", indent);
+    let mut empty = true;
+    for link in links {
+        let _ = writeln!(xml, "{}        <li>{}</li>", indent, link);
+        empty = false;
+    }
+    let _ = write!(xml, "{}    </ol>\n{}</nav>", indent, indent);
+
+    (!empty).then_some(xml)
+}
+
+/// `ol` of the table of contents, its lines after the first indented by `indent`
+pub(crate) fn list(nodes: &[TocNode], doc_path: &str, indent: &str) -> String {
+    let mut xml = String::new();
+    write_list(&mut xml, nodes, doc_path, indent);
+
+    xml.trim().to_string()
+}
+
+/// Whether `node` gets a list item: a heading without a link needs a linked entry below it,
+/// as a `span` must be followed by a nested list.
+pub(crate) fn is_listed(node: &TocNode) -> bool {
+    node.href.is_some() || node.children.iter().flatten().any(is_listed)
+}
+
+fn write_list(xml: &mut String, nodes: &[TocNode], doc_path: &str, indent: &str) {
+    let _ = writeln!(xml, "{}<ol>", indent);
+    for node in nodes.iter().filter(|node| is_listed(node)) {
+        let title = escape(&node.title);
+        let _ = write!(xml, "{}    <li>", indent);
+        match &node.href {
+            Some(target) => {
+                let _ = write!(
+                    xml,
+                    r#"<a href="{}">{}</a>"#,
+                    escape(&href(doc_path, target)),
+                    title
+                );
+            }
+            None => {
+                let _ = write!(xml, "<span>{}</span>", title);
+            }
+        }
+        match node.children.as_deref() {
+            Some(children) if children.iter().any(is_listed) => {
+                xml.push('\n');
+                write_list(xml, children, doc_path, &format!("{}        ", indent));
+                let _ = writeln!(xml, "{}    </li>", indent);
+            }
+            _ => xml.push_str("</li>\n"),
+        }
+    }
+    let _ = writeln!(xml, "{}</ol>", indent);
+}
+
+/// Serialize an NCX at `doc_path` for EPUB 2 reading systems.
+pub(crate) fn ncx(
+    toc: &Toc,
+    metadata: &Metadata,
+    unique_identifier: &str,
+    doc_path: &str,
+) -> String {
+    let mut xml = String::new();
+    let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        xml,
+        r#"<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">"#
+    );
+    xml.push_str("    <head>\n");
+    for (name, content) in [
+        ("dtb:uid", unique_identifier.to_string()),
+        ("dtb:depth", depth(&toc.contents).max(1).to_string()),
+        ("dtb:totalPageCount", toc.page_list.len().to_string()),
+        (
+            "dtb:maxPageNumber",
+            toc.page_list
+                .iter()
+                .filter_map(|page| page.value.or_else(|| page.label.parse().ok()))
+                .max()
+                .unwrap_or_default()
+                .to_string(),
+        ),
+    ] {
+        let _ = writeln!(
+            xml,
+            r#"        <meta name="{}" content="{}"/>"#,
+            name,
+            escape(&content)
+        );
+    }
+    xml.push_str("    </head>\n");
+
+    let _ = writeln!(
+        xml,
+        "    <docTitle><text>{}</text></docTitle>",
+        escape(metadata.title().unwrap_or_default())
+    );
+    for creator in &metadata.creators {
+        let _ = writeln!(
+            xml,
+            "    <docAuthor><text>{}</text></docAuthor>",
+            escape(&creator.name)
+        );
+    }
+
+    let mut play_order = 0;
+    xml.push_str("    <navMap>\n");
+    write_nav_points(&mut xml, &toc.contents, doc_path, &mut play_order, 2);
+    xml.push_str("    </navMap>\n");
+
+    if !toc.page_list.is_empty() {
+        xml.push_str("    <pageList>\n");
+        for (i, page) in toc.page_list.iter().enumerate() {
+            play_order += 1;
+            let value = page.value.or_else(|| page.label.parse().ok());
+            let page_type = page.page_type.as_deref().unwrap_or(match value {
+                Some(_) => "normal",
+                None => "front",
+            });
+            let _ = write!(
+                xml,
+                r#"        <pageTarget id="{}" type="{}""#,
+                escape(&page.id.clone().unwrap_or_else(|| format!("page-{}", i + 1))),
+                escape(page_type)
+            );
+            if let Some(value) = value {
+                let _ = write!(xml, r#" value="{}""#, value);
+            }
+            let _ = writeln!(
+                xml,
+                r#" playOrder="{}"><navLabel><text>{}</text></navLabel><content src="{}"/></pageTarget>"#,
+                play_order,
+                escape(&page.label),
+                escape(&href(doc_path, &page.href))
+            );
+        }
+        xml.push_str("    </pageList>\n");
+    }
+
+    for nav_list in &toc.nav_lists {
+        xml.push_str("    <navList");
+        for (name, value) in [("id", &nav_list.id), ("class", &nav_list.class)] {
+            if let Some(value) = value {
+                let _ = write!(xml, r#" {}="{}""#, name, escape(value));
+            }
+        }
+        let _ = writeln!(
+            xml,
+            "><navLabel><text>{}</text></navLabel>",
+            escape(&nav_list.title)
+        );
+        for (i, target) in nav_list.targets.iter().enumerate() {
+            let Some(target_href) = first_href(target) else {
+                continue;
+            };
+            play_order += 1;
+            let id = target.id.clone().unwrap_or_else(|| {
+                format!(
+                    "{}-{}",
+                    nav_list.id.as_deref().unwrap_or("navTarget"),
+                    i + 1
+                )
+            });
+            let _ = writeln!(
+                xml,
+                r#"        <navTarget id="{}" playOrder="{}"><navLabel><text>{}</text></navLabel><content src="{}"/></navTarget>"#,
+                escape(&id),
+                play_order,
+                escape(&target.title),
+                escape(&href(doc_path, target_href))
+            );
+        }
+        xml.push_str("    </navList>\n");
+    }
+
+    xml.push_str("</ncx>\n");
+    xml
+}
+
+fn write_nav_points(
+    xml: &mut String,
+    nodes: &[TocNode],
+    doc_path: &str,
+    play_order: &mut u32,
+    depth: usize,
+) {
+    let indent = "    ".repeat(depth);
+    for node in nodes {
+        // `content` is required, nodes without a link point to their first child's target
+        let Some(target) = first_href(node) else {
+            continue;
+        };
+        *play_order += 1;
+        let _ = writeln!(
+            xml,
+            r#"{}<navPoint id="{}" playOrder="{}">"#,
+            indent,
+            escape(
+                &node
+                    .id
+                    .clone()
+                    .unwrap_or_else(|| format!("navPoint-{}", play_order))
+            ),
+            play_order
+        );
+        let _ = writeln!(
+            xml,
+            r#"{}    <navLabel><text>{}</text></navLabel>"#,
+            indent,
+            escape(&node.title)
+        );
+        let _ = writeln!(
+            xml,
+            r#"{}    <content src="{}"/>"#,
+            indent,
+            escape(&href(doc_path, target))
+        );
+        if let Some(children) = &node.children {
+            write_nav_points(xml, children, doc_path, play_order, depth + 1);
+        }
+        let _ = writeln!(xml, "{}</navPoint>", indent);
+    }
+}
+
+fn first_href(node: &TocNode) -> Option<&Href> {
+    node.href
+        .as_ref()
+        .or_else(|| node.children.as_ref()?.iter().find_map(first_href))
+}
+
+fn depth(nodes: &[TocNode]) -> usize {
+    nodes
+        .iter()
+        .map(|node| 1 + node.children.as_deref().map_or(0, depth))
+        .max()
+        .unwrap_or_default()
+}
+
+/// `target` relative to the document at `doc_path`
+fn href(doc_path: &str, target: &Href) -> String {
+    if path::is_remote(&target.path) {
+        return target.to_string();
+    }
+
+    path::relative(doc_path, &target.to_string())
+}
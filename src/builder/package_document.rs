@@ -0,0 +1,688 @@
+use std::collections::hash_map::RandomState;
+use std::fmt::Write;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::parser::{
+    AlternateScript, ManifestItem, Meta, Metadata, PageProgressionDirection, Spine, SpineItem,
+    ONIX_CODELIST5,
+};
+use crate::util::xml_util::escape;
+
+const NAMESPACE_DC: &str = "http://purl.org/dc/elements/1.1/";
+pub(crate) const NAMESPACE_OPF: &str = "http://www.idpf.org/2007/opf";
+
+/// Dublin Core elements of `Metadata`, in the order they are written
+pub(crate) const DC_ELEMENTS: [&str; 15] = [
+    "identifier",
+    "title",
+    "language",
+    "creator",
+    "contributor",
+    "publisher",
+    "date",
+    "description",
+    "subject",
+    "rights",
+    "source",
+    "type",
+    "format",
+    "relation",
+    "coverage",
+];
+/// refinements written from the typed metadata fields, dropped from `Metadata::metas`
+pub(crate) const REFINEMENTS: [&str; 6] = [
+    "title-type",
+    "file-as",
+    "display-seq",
+    "alternate-script",
+    "role",
+    "identifier-type",
+];
+/// prefixes reserved by EPUB 3, usable in `property` without a `prefix` declaration
+const RESERVED_PREFIXES: [&str; 10] = [
+    "a11y",
+    "dcterms",
+    "marc",
+    "media",
+    "msv",
+    "onix",
+    "prism",
+    "rendition",
+    "schema",
+    "xsd",
+];
+/// the default vocabulary of `meta` properties
+const META_PROPERTIES: [&str; 13] = [
+    "alternate-script",
+    "authority",
+    "belongs-to-collection",
+    "collection-type",
+    "display-seq",
+    "file-as",
+    "group-position",
+    "identifier-type",
+    "meta-auth",
+    "role",
+    "source-of",
+    "term",
+    "title-type",
+];
+
+/// Namespace prefixes metadata elements are written with, and whether EPUB 2 `opf:`
+/// attributes replace EPUB 3 refinements.
+#[derive(Debug, Clone)]
+pub(crate) struct MetadataStyle {
+    pub dc: Prefix,
+    /// `Some` for EPUB 2 package documents
+    pub opf: Option<Prefix>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Prefix {
+    pub name: String,
+    /// whether the element has to declare the namespace itself
+    pub declare: bool,
+}
+
+impl Default for MetadataStyle {
+    fn default() -> MetadataStyle {
+        MetadataStyle {
+            dc: Prefix {
+                name: String::from("dc"),
+                declare: false,
+            },
+            opf: None,
+        }
+    }
+}
+
+/// Serialize the package document, with manifest hrefs already relative to it.
+/// `metadata.modified` is expected to be set, and the unique identifier to have an id.
+pub(super) fn write(metadata: &Metadata, manifest: &[ManifestItem], spine: &Spine) -> String {
+    let mut xml = String::new();
+    let lang = metadata
+        .language()
+        .map(|lang| format!(r#" xml:lang="{}""#, escape(lang)))
+        .unwrap_or_default();
+    let unique_identifier_id = metadata
+        .identifiers
+        .iter()
+        .find(|identifier| Some(&identifier.value) == metadata.unique_identifier.as_ref())
+        .and_then(|identifier| identifier.id.as_deref())
+        .unwrap_or_default();
+    let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        xml,
+        r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="{}"{}>"#,
+        escape(unique_identifier_id),
+        lang
+    );
+
+    write_metadata(&mut xml, metadata, manifest);
+
+    xml.push_str("    <manifest>\n");
+    for item in manifest {
+        let _ = writeln!(xml, "        {}", manifest_item(item));
+    }
+    xml.push_str("    </manifest>\n");
+
+    xml.push_str("    <spine");
+    if let Some(toc) = &spine.toc {
+        let _ = write!(xml, r#" toc="{}""#, escape(toc));
+    }
+    match spine.page_progression_direction {
+        PageProgressionDirection::Ltr => xml.push_str(r#" page-progression-direction="ltr""#),
+        PageProgressionDirection::Rtl => xml.push_str(r#" page-progression-direction="rtl""#),
+        PageProgressionDirection::Default => {}
+    }
+    xml.push_str(">\n");
+    for item in &spine.items {
+        let _ = writeln!(xml, "        {}", itemref(item));
+    }
+    xml.push_str("    </spine>\n");
+    xml.push_str("</package>\n");
+
+    xml
+}
+
+fn write_metadata(xml: &mut String, metadata: &Metadata, manifest: &[ManifestItem]) {
+    let style = MetadataStyle::default();
+    let mut ids = Ids::new(metadata).excluding(manifest.iter().map(|item| item.id.as_str()));
+    let mut refinements = vec![];
+
+    xml.push_str("    <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n");
+    for name in DC_ELEMENTS {
+        let (elements, element_refinements) = dc_elements(metadata, name, &style, &mut ids);
+        for element in elements {
+            let _ = writeln!(xml, "        {}", element);
+        }
+        refinements.extend(element_refinements);
+    }
+    if let Some(modified) = &metadata.modified {
+        let _ = writeln!(xml, "        {}", modified_element(modified));
+    }
+    for refinement in refinements {
+        let _ = writeln!(xml, "        {}", refinement);
+    }
+
+    let mut has_cover_meta = false;
+    for meta in &metadata.metas {
+        if meta.refines.is_some() && REFINEMENTS.contains(&meta.property.as_str()) {
+            continue;
+        }
+        if meta.refines.is_none() && meta.property == "dcterms:modified" {
+            continue;
+        }
+        has_cover_meta |= meta.property == "cover";
+        let _ = writeln!(xml, "        {}", meta_element(meta));
+    }
+
+    // EPUB 2 reading systems only find the cover through `<meta name="cover">`
+    let cover = manifest
+        .iter()
+        .find(|item| item.properties.iter().any(|p| p == "cover-image"));
+    if let (Some(cover), false) = (cover, has_cover_meta) {
+        let _ = writeln!(
+            xml,
+            r#"        <meta name="cover" content="{}"/>"#,
+            escape(&cover.id)
+        );
+    }
+
+    xml.push_str("    </metadata>\n");
+}
+
+/// The `dc:{name}` elements of `metadata`, and the EPUB 3 refinements of them.
+pub(crate) fn dc_elements(
+    metadata: &Metadata,
+    name: &str,
+    style: &MetadataStyle,
+    ids: &mut Ids,
+) -> (Vec<String>, Vec<String>) {
+    let mut elements = vec![];
+    let mut refinements = vec![];
+    let element =
+        |id: Option<&str>, lang: Option<&str>, attributes: &[(String, String)], value: &str| {
+            dc_element(style, name, id, lang, attributes, value)
+        };
+
+    match name {
+        "identifier" => {
+            for identifier in &metadata.identifiers {
+                let scheme = identifier.scheme.as_deref();
+                if let Some(opf) = &style.opf {
+                    let attributes: Vec<(&str, &str)> = scheme
+                        .map(|scheme| ("scheme", scheme))
+                        .into_iter()
+                        .collect();
+                    elements.push(element(
+                        identifier.id.as_deref(),
+                        None,
+                        &opf_attributes(opf, &attributes),
+                        &identifier.value,
+                    ));
+                    continue;
+                }
+
+                let identifier_type =
+                    scheme.and_then(|scheme| identifier_type(scheme, &identifier.value));
+                let id = match identifier_type {
+                    Some(_) => Some(
+                        identifier
+                            .id
+                            .clone()
+                            .unwrap_or_else(|| ids.next("identifier")),
+                    ),
+                    None => identifier.id.clone(),
+                };
+                elements.push(element(id.as_deref(), None, &[], &identifier.value));
+                if let (Some(id), Some((value, scheme))) = (&id, identifier_type) {
+                    refinements.push(refine(id, "identifier-type", scheme, None, value));
+                }
+            }
+        }
+        "title" => {
+            for title in &metadata.titles {
+                let refined = title.title_type.is_some()
+                    || title.file_as.is_some()
+                    || title.display_seq.is_some()
+                    || !title.alternate_scripts.is_empty();
+                let id = match (&title.id, refined && style.opf.is_none()) {
+                    (Some(id), _) => Some(id.clone()),
+                    (None, true) => Some(ids.next("title")),
+                    (None, false) => None,
+                };
+                elements.push(element(
+                    id.as_deref(),
+                    title.lang.as_deref(),
+                    &[],
+                    &title.value,
+                ));
+                let (Some(id), None) = (id, &style.opf) else {
+                    continue;
+                };
+                if let Some(title_type) = &title.title_type {
+                    refinements.push(refine(&id, "title-type", None, None, title_type));
+                }
+                if let Some(file_as) = &title.file_as {
+                    refinements.push(refine(&id, "file-as", None, None, file_as));
+                }
+                if let Some(display_seq) = title.display_seq {
+                    refinements.push(refine(
+                        &id,
+                        "display-seq",
+                        None,
+                        None,
+                        &display_seq.to_string(),
+                    ));
+                }
+                refinements.extend(alternate_scripts(&id, &title.alternate_scripts));
+            }
+        }
+        "creator" | "contributor" => {
+            let creators = match name {
+                "creator" => &metadata.creators,
+                _ => &metadata.contributors,
+            };
+            for creator in creators {
+                if let Some(opf) = &style.opf {
+                    let attributes: Vec<(&str, &str)> =
+                        [("role", &creator.role), ("file-as", &creator.file_as)]
+                            .into_iter()
+                            .filter_map(|(name, value)| Some((name, value.as_deref()?)))
+                            .collect();
+                    elements.push(element(
+                        creator.id.as_deref(),
+                        creator.lang.as_deref(),
+                        &opf_attributes(opf, &attributes),
+                        &creator.name,
+                    ));
+                    continue;
+                }
+
+                let refined = creator.role.is_some()
+                    || creator.file_as.is_some()
+                    || creator.display_seq.is_some()
+                    || !creator.alternate_scripts.is_empty();
+                let id = creator
+                    .id
+                    .clone()
+                    .or_else(|| refined.then(|| ids.next(name)));
+                elements.push(element(
+                    id.as_deref(),
+                    creator.lang.as_deref(),
+                    &[],
+                    &creator.name,
+                ));
+                let Some(id) = id else {
+                    continue;
+                };
+                if let Some(role) = &creator.role {
+                    refinements.push(refine(&id, "role", Some("marc:relators"), None, role));
+                }
+                if let Some(file_as) = &creator.file_as {
+                    refinements.push(refine(&id, "file-as", None, None, file_as));
+                }
+                if let Some(display_seq) = creator.display_seq {
+                    refinements.push(refine(
+                        &id,
+                        "display-seq",
+                        None,
+                        None,
+                        &display_seq.to_string(),
+                    ));
+                }
+                refinements.extend(alternate_scripts(&id, &creator.alternate_scripts));
+            }
+        }
+        "date" => {
+            if let Some(opf) = &style.opf {
+                for date in &metadata.dates {
+                    let attributes: Vec<(&str, &str)> = date
+                        .event
+                        .as_deref()
+                        .map(|event| ("event", event))
+                        .into_iter()
+                        .collect();
+                    elements.push(element(
+                        date.id.as_deref(),
+                        None,
+                        &opf_attributes(opf, &attributes),
+                        &date.value,
+                    ));
+                }
+            } else {
+                // EPUB 3 allows a single `dc:date`, the publication date
+                let date = metadata
+                    .dates
+                    .iter()
+                    .find(|date| matches!(date.event.as_deref(), None | Some("publication")))
+                    .or(metadata.dates.first());
+                if let Some(date) = date {
+                    elements.push(element(date.id.as_deref(), None, &[], &date.value));
+                }
+            }
+        }
+        name => {
+            let values = match name {
+                "language" => &metadata.languages,
+                "publisher" => &metadata.publishers,
+                "description" => &metadata.descriptions,
+                "subject" => &metadata.subjects,
+                "rights" => &metadata.rights,
+                "source" => &metadata.sources,
+                "type" => &metadata.types,
+                "format" => &metadata.formats,
+                "relation" => &metadata.relations,
+                "coverage" => &metadata.coverages,
+                _ => return (elements, refinements),
+            };
+            elements.extend(values.iter().map(|value| element(None, None, &[], value)));
+        }
+    }
+
+    (elements, refinements)
+}
+
+fn dc_element(
+    style: &MetadataStyle,
+    name: &str,
+    id: Option<&str>,
+    lang: Option<&str>,
+    attributes: &[(String, String)],
+    value: &str,
+) -> String {
+    let mut xml = format!("<{}:{}", style.dc.name, name);
+    if style.dc.declare {
+        let _ = write!(xml, r#" xmlns:{}="{}""#, style.dc.name, NAMESPACE_DC);
+    }
+    if let Some(id) = id {
+        let _ = write!(xml, r#" id="{}""#, escape(id));
+    }
+    for (name, value) in attributes {
+        let _ = write!(xml, r#" {}="{}""#, name, escape(value));
+    }
+    if let Some(lang) = lang {
+        let _ = write!(xml, r#" xml:lang="{}""#, escape(lang));
+    }
+    let _ = write!(xml, ">{}</{}:{}>", escape(value), style.dc.name, name);
+
+    xml
+}
+
+/// EPUB 2 attributes in the OPF namespace, with its declaration if needed
+fn opf_attributes(opf: &Prefix, attributes: &[(&str, &str)]) -> Vec<(String, String)> {
+    let mut qualified: Vec<(String, String)> = attributes
+        .iter()
+        .map(|(name, value)| (format!("{}:{}", opf.name, name), value.to_string()))
+        .collect();
+    if opf.declare && !qualified.is_empty() {
+        qualified.insert(
+            0,
+            (format!("xmlns:{}", opf.name), NAMESPACE_OPF.to_string()),
+        );
+    }
+
+    qualified
+}
+
+fn alternate_scripts(id: &str, alternate_scripts: &[AlternateScript]) -> Vec<String> {
+    alternate_scripts
+        .iter()
+        .map(|script| {
+            refine(
+                id,
+                "alternate-script",
+                None,
+                script.lang.as_deref(),
+                &script.value,
+            )
+        })
+        .collect()
+}
+
+fn refine(
+    id: &str,
+    property: &str,
+    scheme: Option<&str>,
+    lang: Option<&str>,
+    value: &str,
+) -> String {
+    let mut xml = format!(
+        r##"<meta refines="#{}" property="{}""##,
+        escape(id),
+        property
+    );
+    if let Some(scheme) = scheme {
+        let _ = write!(xml, r#" scheme="{}""#, scheme);
+    }
+    if let Some(lang) = lang {
+        let _ = write!(xml, r#" xml:lang="{}""#, escape(lang));
+    }
+    let _ = write!(xml, ">{}</meta>", escape(value));
+
+    xml
+}
+
+pub(crate) fn modified_element(modified: &str) -> String {
+    format!(
+        r#"<meta property="dcterms:modified">{}</meta>"#,
+        escape(modified)
+    )
+}
+
+/// EPUB 3 `meta` for properties of the default vocabulary or a reserved prefix, an EPUB 2
+/// `name`/`content` pair otherwise
+pub(crate) fn meta_element(meta: &Meta) -> String {
+    if !is_epub3_property(&meta.property) {
+        return format!(
+            r#"<meta name="{}" content="{}"/>"#,
+            escape(&meta.property),
+            escape(&meta.value)
+        );
+    }
+
+    let mut xml = String::from("<meta");
+    if let Some(id) = &meta.id {
+        let _ = write!(xml, r#" id="{}""#, escape(id));
+    }
+    if let Some(refines) = &meta.refines {
+        let _ = write!(xml, r##" refines="#{}""##, escape(refines));
+    }
+    let _ = write!(xml, r#" property="{}""#, escape(&meta.property));
+    if let Some(scheme) = &meta.scheme {
+        let _ = write!(xml, r#" scheme="{}""#, escape(scheme));
+    }
+    if let Some(lang) = &meta.lang {
+        let _ = write!(xml, r#" xml:lang="{}""#, escape(lang));
+    }
+    let _ = write!(xml, ">{}</meta>", escape(&meta.value));
+
+    xml
+}
+
+pub(crate) fn manifest_item(item: &ManifestItem) -> String {
+    let mut xml = format!(
+        r#"<item id="{}" href="{}" media-type="{}""#,
+        escape(&item.id),
+        escape(&item.href),
+        escape(
+            item.media_type
+                .as_deref()
+                .unwrap_or("application/octet-stream")
+        )
+    );
+    if !item.properties.is_empty() {
+        let _ = write!(
+            xml,
+            r#" properties="{}""#,
+            escape(&item.properties.join(" "))
+        );
+    }
+    if let Some(fallback) = &item.fallback {
+        let _ = write!(xml, r#" fallback="{}""#, escape(fallback));
+    }
+    if let Some(media_overlay) = &item.media_overlay {
+        let _ = write!(xml, r#" media-overlay="{}""#, escape(media_overlay));
+    }
+    xml.push_str("/>");
+
+    xml
+}
+
+fn itemref(item: &SpineItem) -> String {
+    let mut xml = format!(r#"<itemref idref="{}""#, escape(&item.idref));
+    if let Some(id) = &item.id {
+        let _ = write!(xml, r#" id="{}""#, escape(id));
+    }
+    if !item.linear {
+        xml.push_str(r#" linear="no""#);
+    }
+    if !item.properties.is_empty() {
+        let _ = write!(
+            xml,
+            r#" properties="{}""#,
+            escape(&item.properties.join(" "))
+        );
+    }
+    xml.push_str("/>");
+
+    xml
+}
+
+/// `identifier-type` refinement value and its `scheme` for identifier schemes not evident from
+/// the identifier itself. Known schemes get their ONIX code list 5 value, other schemes are
+/// written as they are.
+fn identifier_type<'s>(scheme: &'s str, value: &str) -> Option<(&'s str, Option<&'static str>)> {
+    let value = value.to_ascii_lowercase();
+    if value.starts_with("urn:") || value.starts_with("doi:") {
+        return None;
+    }
+
+    let digits = value.chars().filter(|c| c.is_ascii_alphanumeric()).count();
+    let code = match scheme.to_ascii_uppercase().as_str() {
+        "ISBN" if digits == 10 => "02",
+        "ISBN" => "15",
+        "GTIN" | "EAN" if digits == 14 => "14",
+        "GTIN" | "EAN" => "03",
+        "UPC" => "04",
+        "ISMN" if digits == 10 => "05",
+        "ISMN" => "25",
+        "DOI" => "06",
+        "LCCN" => "13",
+        "URN" => "22",
+        "OCLC" => "23",
+        "ARK" => "35",
+        // an ONIX code read as is
+        _ if scheme.len() == 2 && scheme.bytes().all(|byte| byte.is_ascii_digit()) => scheme,
+        _ => return Some((scheme, None)),
+    };
+
+    Some((code, Some(ONIX_CODELIST5)))
+}
+
+/// whether `property` is valid in an EPUB 3 `meta@property` without a `prefix` declaration
+fn is_epub3_property(property: &str) -> bool {
+    match property.split_once(':') {
+        Some((prefix, _)) => RESERVED_PREFIXES.contains(&prefix),
+        None => META_PROPERTIES.contains(&property),
+    }
+}
+
+/// Generates element ids not taken by the metadata yet.
+pub(crate) struct Ids {
+    count: usize,
+    taken: Vec<String>,
+}
+
+impl Ids {
+    pub fn new(metadata: &Metadata) -> Ids {
+        let taken = metadata
+            .identifiers
+            .iter()
+            .map(|identifier| &identifier.id)
+            .chain(metadata.titles.iter().map(|title| &title.id))
+            .chain(metadata.creators.iter().map(|creator| &creator.id))
+            .chain(metadata.contributors.iter().map(|creator| &creator.id))
+            .chain(metadata.dates.iter().map(|date| &date.id))
+            .chain(metadata.metas.iter().map(|meta| &meta.id))
+            .flatten()
+            .cloned()
+            .collect();
+
+        Ids { count: 0, taken }
+    }
+
+    /// Also keep clear of `ids`, e.g. those of the manifest.
+    pub fn excluding<'i>(mut self, ids: impl IntoIterator<Item = &'i str>) -> Ids {
+        self.taken.extend(ids.into_iter().map(str::to_string));
+        self
+    }
+
+    pub fn next(&mut self, name: &str) -> String {
+        loop {
+            self.count += 1;
+            let id = format!("{}-{}", name, self.count);
+            if !self.taken.contains(&id) {
+                return id;
+            }
+        }
+    }
+}
+
+/// current UTC time as required by `dcterms:modified`, e.g. `2024-01-01T00:00:00Z`
+pub(crate) fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (days, secs) = ((secs / 86400) as i64, secs % 86400);
+
+    // civil date from days since the epoch, http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// random version 4 UUID
+pub(super) fn uuid() -> String {
+    let mut bytes = [0u8; 16];
+    for (i, chunk) in bytes.chunks_mut(8).enumerate() {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(i);
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_nanos()),
+        );
+        chunk.copy_from_slice(&hasher.finish().to_be_bytes());
+    }
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
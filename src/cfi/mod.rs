@@ -0,0 +1,617 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use roxmltree::{Document, Node};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::parser::{Spine, SpineItem};
+
+mod parse;
+
+/// step of the `spine` element in the package document, after `metadata` and `manifest`
+const SPINE_STEP: usize = 6;
+
+/// An [EPUB Canonical Fragment Identifier](https://idpf.org/epub/linking/cfi/), e.g.
+/// `epubcfi(/6/4[chap01ref]!/4[body01]/10[para05]/3:10)`.
+///
+/// Character offsets count UTF-16 code units, like the DOM of web based reading systems.
+///
+/// ```no_run
+/// use ezpub::cfi::Cfi;
+/// use ezpub::parser::Parser;
+///
+/// let mut parser = Parser::open("sample.epub").unwrap();
+/// let spine = parser.meta().unwrap().spine;
+///
+/// let cfi: Cfi = "epubcfi(/6/4!/4/10/3:10)".parse().unwrap();
+/// let (_, item) = cfi.spine_item(&spine).unwrap();
+/// let doc = String::from_utf8(parser.resource(&item.path).unwrap()).unwrap();
+/// let doc = roxmltree::Document::parse(&doc).unwrap();
+/// let position = cfi.resolve(&doc).unwrap();
+/// println!("{:?} {:?}", position.node.text(), position.offset);
+/// ```
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Cfi {
+    /// the location, or the path shared by both ends of a range
+    pub path: Path,
+    /// start and end of a range, relative to `path`
+    pub range: Option<(Path, Path)>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Path {
+    pub steps: Vec<Step>,
+    /// only after the last step
+    pub offset: Option<Offset>,
+}
+
+/// `/index[assertion]`: even indexes step into an element child, odd ones into the text
+/// before, between or after them
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Step {
+    pub index: usize,
+    /// id the target element is expected to have, e.g. an itemref or element id
+    pub assertion: Option<String>,
+    /// preceded by `!`: the step is the first in the document the previous one references
+    pub indirect: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Offset {
+    /// `:offset`, into a text node
+    Character {
+        offset: usize,
+        assertion: Option<TextAssertion>,
+    },
+    /// `~seconds`, into audio or video, optionally `@x:y` too
+    Temporal {
+        seconds: f64,
+        position: Option<(f64, f64)>,
+    },
+    /// `@x:y`, into an image, in percent of its size
+    Spatial { x: f64, y: f64 },
+}
+
+/// `[before,after;s=b]`: text expected around a character offset, and the side the location
+/// sticks to
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TextAssertion {
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub side: Option<Side>,
+}
+
+/// side bias: whether a location belongs to the character before or after it, e.g. across a
+/// line or page break
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Side {
+    Before,
+    After,
+}
+
+/// node a CFI resolves to in a content document
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Position<'a, 'input> {
+    /// an element, or the text node holding the location
+    pub node: Node<'a, 'input>,
+    /// byte offset in the text of `node`, for a character offset
+    pub offset: Option<usize>,
+}
+
+impl Cfi {
+    pub fn parse(cfi: &str) -> Result<Cfi> {
+        parse::parse(cfi)
+    }
+
+    /// CFI of a location in the spine item at `spine_index`: `node`, at byte `offset` of its
+    /// text for a text node. `offset` is ignored for elements. `None` if `spine_index` is out
+    /// of bounds.
+    pub fn generate(
+        spine: &Spine,
+        spine_index: usize,
+        node: &Node,
+        offset: Option<usize>,
+    ) -> Option<Cfi> {
+        let item = spine.items.get(spine_index)?;
+        let mut steps = Vec::new();
+        let mut character_offset = None;
+
+        let mut node = *node;
+        if node.is_text() {
+            let text = node.text().unwrap_or_default();
+            let offset = floor_char_boundary(text, offset.unwrap_or_default().min(text.len()));
+            // the text step covers every text node between two elements
+            let preceding: usize = node
+                .prev_siblings()
+                .skip(1)
+                .take_while(|sibling| !sibling.is_element())
+                .map(|sibling| utf16_len(sibling.text().unwrap_or_default()))
+                .sum();
+            let elements = node
+                .prev_siblings()
+                .filter(|node| node.is_element())
+                .count();
+            character_offset = Some(Offset::Character {
+                offset: preceding + utf16_len(&text[..offset]),
+                assertion: None,
+            });
+            steps.push(Step {
+                index: elements * 2 + 1,
+                ..Default::default()
+            });
+            node = node.parent()?;
+        }
+        while let Some(parent) = node.parent_element() {
+            let elements = node
+                .prev_siblings()
+                .filter(|node| node.is_element())
+                .count();
+            steps.push(Step {
+                index: elements * 2,
+                assertion: node.attribute("id").map(|id| id.to_string()),
+                indirect: false,
+            });
+            node = parent;
+        }
+        if let Some(first) = steps.last_mut() {
+            first.indirect = true;
+        }
+        steps.extend(item_steps(spine_index, item));
+        steps.reverse();
+
+        Some(Cfi {
+            path: Path {
+                steps,
+                offset: character_offset,
+            },
+            range: None,
+        })
+    }
+
+    /// Range from `start` to `end`, sharing their common steps.
+    pub fn range(start: &Cfi, end: &Cfi) -> Cfi {
+        let (start, end) = (start.start().path, end.start().path);
+        // each end keeps at least its last step
+        let common = start
+            .steps
+            .iter()
+            .zip(&end.steps)
+            .take(start.steps.len().min(end.steps.len()).saturating_sub(1))
+            .take_while(|(start, end)| start == end)
+            .count();
+
+        Cfi {
+            path: Path {
+                steps: start.steps[..common].to_vec(),
+                offset: None,
+            },
+            range: Some((
+                Path {
+                    steps: start.steps[common..].to_vec(),
+                    offset: start.offset,
+                },
+                Path {
+                    steps: end.steps[common..].to_vec(),
+                    offset: end.offset,
+                },
+            )),
+        }
+    }
+
+    /// the start of a range, or the location itself
+    pub fn start(&self) -> Cfi {
+        self.collapse(|(start, _)| start)
+    }
+
+    /// the end of a range, or the location itself
+    pub fn end(&self) -> Cfi {
+        self.collapse(|(_, end)| end)
+    }
+
+    fn collapse(&self, select: impl Fn(&(Path, Path)) -> &Path) -> Cfi {
+        match &self.range {
+            Some(range) => {
+                let local = select(range);
+                let mut steps = self.path.steps.clone();
+                steps.extend(local.steps.iter().cloned());
+                Cfi {
+                    path: Path {
+                        steps,
+                        offset: local.offset.clone(),
+                    },
+                    range: None,
+                }
+            }
+            None => self.clone(),
+        }
+    }
+
+    /// Index of the spine item the CFI points into, from the itemref step, e.g. the `/4` of
+    /// `/6/4!/4/2`.
+    pub fn spine_index(&self) -> Option<usize> {
+        let step = self.start().path.steps.get(1).cloned()?;
+        (!step.indirect && step.index >= 2 && step.index % 2 == 0).then(|| step.index / 2 - 1)
+    }
+
+    /// Spine item the CFI points into. An itemref id assertion wins over the index, which may
+    /// be stale after the spine changed.
+    pub fn spine_item<'a>(&self, spine: &'a Spine) -> Option<(usize, &'a SpineItem)> {
+        let start = self.start();
+        let asserted = start
+            .path
+            .steps
+            .get(1)
+            .and_then(|step| step.assertion.as_deref())
+            .and_then(|id| {
+                spine
+                    .iter()
+                    .position(|item| item.id.as_deref() == Some(id) || item.idref == id)
+            });
+        let index = asserted.or_else(|| self.spine_index())?;
+
+        Some((index, spine.items.get(index)?))
+    }
+
+    /// Resolve the start of the CFI in the content document `doc`. Element id assertions win
+    /// over indexes, which may be stale after the document changed.
+    pub fn resolve<'a, 'input>(&self, doc: &'a Document<'input>) -> Option<Position<'a, 'input>> {
+        let path = self.start().path;
+        let first = path.steps.iter().position(|step| step.indirect)?;
+        let steps = &path.steps[first..];
+
+        let mut node = doc.root_element();
+        for (i, step) in steps.iter().enumerate() {
+            if step.index % 2 == 1 {
+                // a text step is always the last one
+                if i + 1 != steps.len() {
+                    return None;
+                }
+                return Some(resolve_text(node, step.index / 2, path.offset.as_ref()));
+            }
+
+            let child = node
+                .children()
+                .filter(|node| node.is_element())
+                .nth((step.index / 2).checked_sub(1)?);
+            node = match &step.assertion {
+                Some(id) if child.and_then(|child| child.attribute("id")) != Some(id.as_str()) => {
+                    doc.descendants()
+                        .find(|node| node.attribute("id") == Some(id.as_str()))
+                        .or(child)?
+                }
+                _ => child?,
+            };
+        }
+
+        Some(Position { node, offset: None })
+    }
+
+    /// Order by position in the book, ranges by their start. Assertions don't count.
+    pub fn compare(&self, other: &Cfi) -> Ordering {
+        let (start, other_start) = (self.start().path, other.start().path);
+        let steps = start
+            .steps
+            .iter()
+            .zip(&other_start.steps)
+            .map(|(step, other_step)| step.index.cmp(&other_step.index))
+            .find(|ordering| ordering.is_ne())
+            // an element starts before its content
+            .unwrap_or_else(|| start.steps.len().cmp(&other_start.steps.len()));
+
+        steps.then_with(|| compare_offsets(start.offset.as_ref(), other_start.offset.as_ref()))
+    }
+}
+
+/// `/6/N[id]!`: the spine and the itemref at `spine_index`, in reverse order
+fn item_steps(spine_index: usize, item: &SpineItem) -> [Step; 2] {
+    [
+        Step {
+            index: (spine_index + 1) * 2,
+            assertion: item.id.clone(),
+            indirect: false,
+        },
+        Step {
+            index: SPINE_STEP,
+            ..Default::default()
+        },
+    ]
+}
+
+/// Resolve a character offset in the text after the `elements`-th element child of `parent`.
+fn resolve_text<'a, 'input>(
+    parent: Node<'a, 'input>,
+    elements: usize,
+    offset: Option<&Offset>,
+) -> Position<'a, 'input> {
+    let mut remaining = match offset {
+        Some(Offset::Character { offset, .. }) => *offset,
+        _ => 0,
+    };
+    let texts = parent
+        .children()
+        .scan(0, |seen, node| {
+            *seen += node.is_element() as usize;
+            Some((*seen, node))
+        })
+        .filter(|(seen, node)| *seen == elements && node.is_text())
+        .map(|(_, node)| node);
+
+    let mut last = None;
+    for node in texts {
+        let text = node.text().unwrap_or_default();
+        let len = utf16_len(text);
+        if remaining <= len {
+            return Position {
+                node,
+                offset: Some(byte_offset(text, remaining)),
+            };
+        }
+        remaining -= len;
+        last = Some(node);
+    }
+
+    match last {
+        // past the end of the text, e.g. after an edit
+        Some(node) => Position {
+            node,
+            offset: node.text().map(|text| text.len()),
+        },
+        None => Position {
+            node: parent,
+            offset: None,
+        },
+    }
+}
+
+fn compare_offsets(offset: Option<&Offset>, other: Option<&Offset>) -> Ordering {
+    let key = |offset: Option<&Offset>| match offset {
+        None => (0, 0.0, 0.0),
+        Some(Offset::Character { offset, .. }) => (1, *offset as f64, 0.0),
+        Some(Offset::Temporal { seconds, .. }) => (2, *seconds, 0.0),
+        Some(Offset::Spatial { x, y }) => (3, *y, *x),
+    };
+    let (kind, primary, secondary) = key(offset);
+    let (other_kind, other_primary, other_secondary) = key(other);
+
+    kind.cmp(&other_kind).then_with(|| {
+        primary
+            .total_cmp(&other_primary)
+            .then_with(|| secondary.total_cmp(&other_secondary))
+    })
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.chars().map(char::len_utf16).sum()
+}
+
+/// byte offset of the UTF-16 code unit `offset` of `text`, rounded down to a character
+fn byte_offset(text: &str, offset: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in text.char_indices() {
+        units += c.len_utf16();
+        if units > offset {
+            return i;
+        }
+    }
+
+    text.len()
+}
+
+fn floor_char_boundary(text: &str, offset: usize) -> usize {
+    (0..=offset)
+        .rev()
+        .find(|i| text.is_char_boundary(*i))
+        .unwrap_or_default()
+}
+
+impl FromStr for Cfi {
+    type Err = Error;
+
+    fn from_str(cfi: &str) -> Result<Cfi> {
+        Cfi::parse(cfi)
+    }
+}
+
+impl Display for Cfi {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "epubcfi({}", self.path)?;
+        if let Some((start, end)) = &self.range {
+            write!(f, ",{},{}", start, end)?;
+        }
+
+        write!(f, ")")
+    }
+}
+
+impl Display for Path {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for step in &self.steps {
+            if step.indirect {
+                write!(f, "!")?;
+            }
+            write!(f, "/{}", step.index)?;
+            if let Some(assertion) = &step.assertion {
+                write!(f, "[{}]", escape(assertion))?;
+            }
+        }
+        match &self.offset {
+            Some(Offset::Character { offset, assertion }) => {
+                write!(f, ":{}", offset)?;
+                if let Some(assertion) = assertion {
+                    write!(f, "{}", assertion)?;
+                }
+            }
+            Some(Offset::Temporal { seconds, position }) => {
+                write!(f, "~{}", seconds)?;
+                if let Some((x, y)) = position {
+                    write!(f, "@{}:{}", x, y)?;
+                }
+            }
+            Some(Offset::Spatial { x, y }) => write!(f, "@{}:{}", x, y)?,
+            None => {}
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for TextAssertion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[")?;
+        if let Some(before) = &self.before {
+            write!(f, "{}", escape(before))?;
+        }
+        if let Some(after) = &self.after {
+            write!(f, ",{}", escape(after))?;
+        }
+        match self.side {
+            Some(Side::Before) => write!(f, ";s=b")?,
+            Some(Side::After) => write!(f, ";s=a")?,
+            None => {}
+        }
+
+        write!(f, "]")
+    }
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if parse::SPECIAL_CHARS.contains(&c) {
+            escaped.push('^');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_serialize() {
+        let cfi: Cfi = "epubcfi(/6/4[chap01ref]!/4[body01]/10[para05],/2/1:1,/3:4[a^,b,c;s=b])"
+            .parse()
+            .unwrap();
+        assert_eq!(Some(1), cfi.spine_index());
+        assert_eq!(
+            Some(TextAssertion {
+                before: Some(String::from("a,b")),
+                after: Some(String::from("c")),
+                side: Some(Side::Before),
+            }),
+            match cfi.end().path.offset {
+                Some(Offset::Character { assertion, .. }) => assertion,
+                _ => None,
+            }
+        );
+        assert_eq!(
+            "epubcfi(/6/4[chap01ref]!/4[body01]/10[para05],/2/1:1,/3:4[a^,b,c;s=b])",
+            cfi.to_string()
+        );
+        assert_eq!(
+            "epubcfi(/6/4[chap01ref]!/4[body01]/10[para05]/2/1:1)",
+            cfi.start().to_string()
+        );
+
+        for cfi in ["epubcfi(/6/4!/4~23.5@50:30)", "epubcfi(/6/2!/4/2@12.5:100)"] {
+            assert_eq!(cfi, Cfi::parse(cfi).unwrap().to_string());
+        }
+        assert!(matches!(
+            Cfi::parse("epubcfi(/6/4!/4[body01/2)"),
+            Err(Error::InvalidCfi { position: 24, .. })
+        ));
+
+        let mut cfis: Vec<Cfi> = [
+            "epubcfi(/6/4!/4/10/3:10)",
+            "epubcfi(/6/4!/4/10)",
+            "epubcfi(/6/2!/4/2/1:0)",
+            "epubcfi(/6/4!/4/10/3:2)",
+            "epubcfi(/6/4!/4/8,/1:0,/1:5)",
+        ]
+        .iter()
+        .map(|cfi| cfi.parse().unwrap())
+        .collect();
+        cfis.sort_by(Cfi::compare);
+        assert_eq!(
+            vec![
+                "epubcfi(/6/2!/4/2/1:0)",
+                "epubcfi(/6/4!/4/8,/1:0,/1:5)",
+                "epubcfi(/6/4!/4/10)",
+                "epubcfi(/6/4!/4/10/3:2)",
+                "epubcfi(/6/4!/4/10/3:10)",
+            ],
+            cfis.iter().map(|cfi| cfi.to_string()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn generate_and_resolve() {
+        let doc = r#"<html xmlns="http://www.w3.org/1999/xhtml">
+<head><title>Chapter 1</title></head>
+<body id="body01">
+    <h1>Chapter 1</h1>
+    <p id="para02">Ünïcode <em>and</em> 𝄞 text</p>
+</body>
+</html>"#;
+        let doc = Document::parse(doc).unwrap();
+        let spine = Spine {
+            items: ["cover", "chapter-1"]
+                .iter()
+                .map(|idref| SpineItem {
+                    id: Some(format!("{}-ref", idref)),
+                    idref: idref.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        let text = doc
+            .descendants()
+            .filter(|node| node.is_text())
+            .find(|node| node.text().unwrap().contains("text"))
+            .unwrap();
+        let cfi = Cfi::generate(&spine, 1, &text, Some(" 𝄞 ".len())).unwrap();
+        assert_eq!(
+            "epubcfi(/6/4[chapter-1-ref]!/4[body01]/4[para02]/3:4)",
+            cfi.to_string()
+        );
+        assert_eq!(
+            Some(Position {
+                node: text,
+                offset: Some(" 𝄞 ".len()),
+            }),
+            cfi.resolve(&doc)
+        );
+
+        // the assertions find the paragraph and the item after an edit
+        let stale: Cfi = "epubcfi(/6/2[chapter-1-ref]!/4[body01]/6[para02])"
+            .parse()
+            .unwrap();
+        assert_eq!(Some(1), stale.spine_item(&spine).map(|(index, _)| index));
+        assert_eq!(
+            Some("para02"),
+            stale
+                .resolve(&doc)
+                .and_then(|position| position.node.attribute("id"))
+        );
+
+        let start = Cfi::generate(&spine, 1, &text.prev_sibling().unwrap(), None).unwrap();
+        assert_eq!(
+            "epubcfi(/6/4[chapter-1-ref]!/4[body01]/4[para02],/2,/3:4)",
+            Cfi::range(&start, &cfi).to_string()
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use crate::cfi::{Cfi, Offset, Path, Side, Step, TextAssertion};
+use crate::error::{Error, Result};
+
+/// characters escaped with `^` in assertion values
+pub(super) const SPECIAL_CHARS: [char; 8] = ['^', '[', ']', '(', ')', ',', ';', '='];
+
+/// Parse `cfi`, with or without the `epubcfi(…)` wrapper and a leading `#`.
+pub(super) fn parse(cfi: &str) -> Result<Cfi> {
+    let trimmed = cfi.trim();
+    let trimmed = trimmed.strip_prefix('#').unwrap_or(trimmed);
+    let (inner, start) = match trimmed
+        .strip_prefix("epubcfi(")
+        .and_then(|inner| inner.strip_suffix(')'))
+    {
+        Some(inner) => (
+            inner,
+            cfi.find("epubcfi(").unwrap_or_default() + "epubcfi(".len(),
+        ),
+        None => (trimmed, cfi.find(trimmed).unwrap_or_default()),
+    };
+
+    let mut reader = Reader {
+        cfi,
+        rest: inner,
+        start,
+        consumed: 0,
+    };
+    let path = reader.path()?;
+    let range = match reader.eat(',') {
+        true => {
+            let start = reader.path()?;
+            reader.expect(',')?;
+            let end = reader.path()?;
+            Some((start, end))
+        }
+        false => None,
+    };
+    if !reader.rest.is_empty() {
+        return Err(reader.error());
+    }
+
+    Ok(Cfi { path, range })
+}
+
+struct Reader<'a> {
+    /// the whole CFI, for errors
+    cfi: &'a str,
+    rest: &'a str,
+    /// byte offset of the unwrapped CFI in `cfi`
+    start: usize,
+    consumed: usize,
+}
+
+impl Reader<'_> {
+    fn path(&mut self) -> Result<Path> {
+        let mut steps = Vec::new();
+        loop {
+            let indirect = self.eat('!');
+            if !self.eat('/') {
+                if indirect {
+                    return Err(self.error());
+                }
+                break;
+            }
+            let index = self.integer()?;
+            let assertion = match self.eat('[') {
+                true => {
+                    let id = self.value();
+                    self.parameters()?;
+                    self.expect(']')?;
+                    (!id.is_empty()).then_some(id)
+                }
+                false => None,
+            };
+            steps.push(Step {
+                index,
+                assertion,
+                indirect,
+            });
+        }
+
+        let offset = match self.peek() {
+            Some(':') => {
+                self.next();
+                let offset = self.integer()?;
+                let assertion = match self.eat('[') {
+                    true => Some(self.text_assertion()?),
+                    false => None,
+                };
+                Some(Offset::Character { offset, assertion })
+            }
+            Some('~') => {
+                self.next();
+                let seconds = self.number()?;
+                let position = match self.eat('@') {
+                    true => Some(self.point()?),
+                    false => None,
+                };
+                Some(Offset::Temporal { seconds, position })
+            }
+            Some('@') => {
+                self.next();
+                let (x, y) = self.point()?;
+                Some(Offset::Spatial { x, y })
+            }
+            _ => None,
+        };
+        if steps.is_empty() && offset.is_none() {
+            return Err(self.error());
+        }
+
+        Ok(Path { steps, offset })
+    }
+
+    /// `[before,after;s=b]`, after the opening bracket
+    fn text_assertion(&mut self) -> Result<TextAssertion> {
+        let before = self.value();
+        let after = match self.eat(',') {
+            true => Some(self.value()),
+            false => None,
+        };
+        let side = self.parameters()?;
+        self.expect(']')?;
+
+        Ok(TextAssertion {
+            before: (!before.is_empty()).then_some(before),
+            after: after.filter(|after| !after.is_empty()),
+            side,
+        })
+    }
+
+    /// `;name=value` parameters, returning the side bias. Others are skipped.
+    fn parameters(&mut self) -> Result<Option<Side>> {
+        let mut side = None;
+        while self.eat(';') {
+            let name = self.value();
+            self.expect('=')?;
+            let value = self.value();
+            if name == "s" {
+                side = match value.as_str() {
+                    "b" => Some(Side::Before),
+                    "a" => Some(Side::After),
+                    _ => return Err(self.error()),
+                };
+            }
+        }
+
+        Ok(side)
+    }
+
+    /// `x:y` of a spatial offset
+    fn point(&mut self) -> Result<(f64, f64)> {
+        let x = self.number()?;
+        self.expect(':')?;
+        let y = self.number()?;
+
+        Ok((x, y))
+    }
+
+    /// an unescaped assertion value, up to the next special character
+    fn value(&mut self) -> String {
+        let mut value = String::new();
+        while let Some(c) = self.peek() {
+            match c {
+                '^' => {
+                    self.next();
+                    if let Some(escaped) = self.next() {
+                        value.push(escaped);
+                    }
+                }
+                c if SPECIAL_CHARS.contains(&c) => break,
+                c => {
+                    self.next();
+                    value.push(c);
+                }
+            }
+        }
+
+        value
+    }
+
+    fn integer(&mut self) -> Result<usize> {
+        let digits = self.take_while(|c| c.is_ascii_digit());
+        digits.parse().map_err(|_| self.error())
+    }
+
+    fn number(&mut self) -> Result<f64> {
+        let digits = self.take_while(|c| c.is_ascii_digit() || c == '.');
+        digits.parse().map_err(|_| self.error())
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> &str {
+        let len = self.rest.find(|c| !predicate(c)).unwrap_or(self.rest.len());
+        let (taken, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        self.consumed += len;
+
+        taken
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest.chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.rest = &self.rest[c.len_utf8()..];
+        self.consumed += c.len_utf8();
+
+        Some(c)
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        let found = self.peek() == Some(expected);
+        if found {
+            self.next();
+        }
+
+        found
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        match self.eat(expected) {
+            true => Ok(()),
+            false => Err(self.error()),
+        }
+    }
+
+    fn error(&self) -> Error {
+        Error::InvalidCfi {
+            cfi: self.cfi.to_string(),
+            position: self.start + self.consumed,
+        }
+    }
+}
@@ -0,0 +1,704 @@
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, Write};
+
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::builder::nav;
+use crate::error::{Error, Result};
+use crate::parser::{Manifest, ManifestItem, Meta, Metadata, Toc, TocSource};
+use crate::util::zip_util::read_text_file;
+use crate::util::{media_type, path};
+
+mod package_document;
+mod splice;
+mod toc;
+
+/// Edits the metadata, manifest and table of contents of a book and writes it back, see
+/// `Parser::edit`.
+///
+/// Entries without changes are copied byte for byte, compressed data included. The package
+/// and navigation documents are edited in place: elements that didn't change, comments and
+/// elements ezpub doesn't know keep their original formatting. Table of contents edits go to the
+/// document the table of contents was read from, the NCX being written anew; books without a
+/// readable one get a new navigation document, or a new NCX for EPUB 2.
+///
+/// ```no_run
+/// use ezpub::parser::{Identifier, Parser};
+///
+/// let mut editor = Parser::open("sample.epub").unwrap().edit().unwrap();
+/// editor.metadata.titles[0].value = String::from("Jane Eyre");
+/// editor.metadata.identifiers.push(Identifier {
+///     value: String::from("urn:isbn:9780141441146"),
+///     ..Default::default()
+/// });
+/// editor.set_cover("EPUB/images/cover.jpg", std::fs::read("cover.jpg").unwrap());
+///
+/// std::fs::write("edited.epub", editor.to_bytes().unwrap()).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Editor<R: Read + Seek = File> {
+    archive: ZipArchive<R>,
+    package_path: String,
+    /// package document as read from the archive
+    package_document: String,
+    /// whether the package document is an EPUB 2 one
+    legacy: bool,
+    toc_source: Option<TocSource>,
+    ncx_path: Option<String>,
+    original_metadata: Metadata,
+    original_manifest: Manifest,
+    original_toc: Toc,
+    /// replaced and added entries, by archive path
+    resources: Entries,
+    pub metadata: Metadata,
+    /// Items are matched to the original ones by id. An item whose `path` changed gets its
+    /// `href` recomputed, and an item whose `href` changed gets its `path` resolved.
+    pub manifest: Manifest,
+    /// Empty when the book's table of contents can't be read. `nav_lists` are read-only,
+    /// edits to them are not written.
+    pub toc: Toc,
+}
+
+/// archive entries by path
+type Entries = Vec<(String, Vec<u8>)>;
+
+/// the parsed package an `Editor` starts from
+pub(crate) struct Package {
+    pub path: String,
+    pub source: String,
+    pub metadata: Metadata,
+    pub manifest: Manifest,
+    pub toc: Toc,
+    /// `None` when the book has no readable table of contents
+    pub toc_source: Option<TocSource>,
+    pub ncx_path: Option<String>,
+}
+
+impl<R: Read + Seek> Editor<R> {
+    pub(crate) fn new(archive: ZipArchive<R>, package: Package) -> Editor<R> {
+        Editor {
+            archive,
+            legacy: roxmltree::Document::parse(&package.source)
+                .is_ok_and(|doc| package_document::is_legacy(&doc.root_element())),
+            package_path: package.path,
+            package_document: package.source,
+            toc_source: package.toc_source,
+            ncx_path: package.ncx_path,
+            original_metadata: package.metadata.clone(),
+            original_manifest: package.manifest.clone(),
+            original_toc: package.toc.clone(),
+            resources: vec![],
+            metadata: package.metadata,
+            manifest: package.manifest,
+            toc: package.toc,
+        }
+    }
+
+    /// Replace the content of the entry at `path`, or add an entry.
+    pub fn set_resource(&mut self, path: &str, data: impl Into<Vec<u8>>) {
+        let data = data.into();
+        match self.resources.iter_mut().find(|(p, _)| p == path) {
+            Some((_, content)) => *content = data,
+            None => self.resources.push((path.to_string(), data)),
+        }
+    }
+
+    /// Add a resource to the manifest. `item.path` is its archive path; when empty it is
+    /// resolved from `item.href` relative to the package document.
+    pub fn add_resource(&mut self, item: ManifestItem, data: impl Into<Vec<u8>>) {
+        let item = self.normalize(item, None);
+        self.set_resource(&item.path, data);
+        self.manifest.items.push(item);
+    }
+
+    /// Remove the manifest item at `path`, its spine entries and its archive entry.
+    pub fn remove_resource(&mut self, path: &str) {
+        self.manifest.items.retain(|item| item.path != path);
+        self.resources.retain(|(p, _)| p != path);
+    }
+
+    /// Replace the cover image with the image at `path`. The manifest item at `path` becomes
+    /// the cover, and is added when there is none. A previous cover image at another path stays
+    /// in the book for the pages still showing it.
+    pub fn set_cover(&mut self, path: &str, data: impl Into<Vec<u8>>) {
+        let legacy_cover = self
+            .metadata
+            .metas
+            .iter()
+            .position(|meta| meta.refines.is_none() && meta.property == "cover");
+        let had_cover = self.manifest.items.iter().any(|item| {
+            item.has_property("cover-image")
+                || legacy_cover.is_some_and(|i| self.metadata.metas[i].value == item.id)
+        });
+
+        for item in self
+            .manifest
+            .items
+            .iter_mut()
+            .filter(|item| item.path != path)
+        {
+            item.properties.retain(|property| property != "cover-image");
+        }
+        let id = match self
+            .manifest
+            .items
+            .iter_mut()
+            .find(|item| item.path == path)
+        {
+            Some(item) => {
+                if !item.has_property("cover-image") {
+                    item.properties.push(String::from("cover-image"));
+                }
+                let id = item.id.clone();
+                self.set_resource(path, data);
+                id
+            }
+            None => {
+                let id = self.unique_id("cover-image");
+                self.add_resource(
+                    ManifestItem {
+                        id: id.clone(),
+                        path: path.to_string(),
+                        properties: vec![String::from("cover-image")],
+                        ..Default::default()
+                    },
+                    data,
+                );
+                id
+            }
+        };
+
+        // EPUB 2 reading systems find the cover through `<meta name="cover">`
+        match legacy_cover {
+            Some(i) => self.metadata.metas[i].value = id,
+            None if !had_cover => self.metadata.metas.push(Meta {
+                property: String::from("cover"),
+                value: id,
+                ..Default::default()
+            }),
+            None => {}
+        }
+    }
+
+    /// Write the edited book to `writer`.
+    ///
+    /// Fails when an added or edited manifest item takes the id or path of another one.
+    pub fn write<W: Write + Seek>(&mut self, writer: W) -> Result<W> {
+        let (files, removed) = self.changes()?;
+
+        let mut zip = ZipWriter::new(writer);
+        let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        // `mimetype` has to come first and uncompressed, whatever the original archive did
+        let mimetype_first = !self.archive.is_empty() && {
+            let file = self.archive.by_index_raw(0).map_err(Error::Archive)?;
+            file.name() == "mimetype" && file.compression() == CompressionMethod::Stored
+        };
+        if !mimetype_first {
+            let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+            zip.start_file("mimetype", stored).map_err(Error::Archive)?;
+            zip.write_all(b"application/epub+zip")?;
+        }
+
+        let mut written = vec![];
+        for i in 0..self.archive.len() {
+            let file = self.archive.by_index_raw(i).map_err(Error::Archive)?;
+            let name = file.name().to_string();
+            if (name == "mimetype" && !(mimetype_first && i == 0)) || removed.contains(&name) {
+                continue;
+            }
+            match files.iter().find(|(path, _)| *path == name) {
+                Some((path, data)) => {
+                    drop(file);
+                    zip.start_file(path.as_str(), deflated)
+                        .map_err(Error::Archive)?;
+                    zip.write_all(data)?;
+                    written.push(name);
+                }
+                None => zip.raw_copy_file(file).map_err(Error::Archive)?,
+            }
+        }
+        for (path, data) in files.iter().filter(|(path, _)| !written.contains(path)) {
+            zip.start_file(path.as_str(), deflated)
+                .map_err(Error::Archive)?;
+            zip.write_all(data)?;
+        }
+
+        zip.finish().map_err(Error::Archive)
+    }
+
+    pub fn to_bytes(&mut self) -> Result<Vec<u8>> {
+        Ok(self.write(Cursor::new(Vec::new()))?.into_inner())
+    }
+
+    /// Contents of the changed and added entries, and the paths of the removed entries.
+    fn changes(&mut self) -> Result<(Entries, Vec<String>)> {
+        let mut manifest: Vec<ManifestItem> = self
+            .manifest
+            .items
+            .iter()
+            .map(|item| {
+                let original = self.original_manifest.by_id(&item.id);
+                self.normalize(item.clone(), original)
+            })
+            .collect();
+        for (i, item) in manifest.iter().enumerate() {
+            // duplicates the book came with are left as they are
+            if self.original_manifest.items.contains(item) {
+                continue;
+            }
+            let others = || manifest.iter().enumerate().filter(move |(j, _)| *j != i);
+            if others().any(|(_, m)| m.id == item.id) {
+                return Err(Error::DuplicateManifestItem {
+                    id: item.id.clone(),
+                });
+            }
+            if others().any(|(_, m)| m.path == item.path) {
+                return Err(Error::DuplicateResource {
+                    path: item.path.clone(),
+                });
+            }
+        }
+        let mut files = vec![];
+
+        let toc = &self.toc;
+        let original_toc = &self.original_toc;
+        let toc_changed = original_toc.contents != toc.contents
+            || original_toc.landmarks != toc.landmarks
+            || original_toc.page_list != toc.page_list;
+        let mut spine_toc = None;
+        if toc_changed {
+            let unique_identifier = self.metadata.unique_identifier.clone().unwrap_or_default();
+            let ncx_toc = Toc {
+                nav_lists: original_toc.nav_lists.clone(),
+                ..toc.clone()
+            };
+            let ncx = |path: &str| nav::ncx(&ncx_toc, &self.metadata, &unique_identifier, path);
+
+            match &self.toc_source {
+                Some(TocSource::NavDoc(path)) => {
+                    let source = read_text_file(&mut self.archive, path)?;
+                    let doc = toc::edit_nav_doc(&source, path, original_toc, toc)?;
+                    if doc != source {
+                        files.push((path.clone(), doc.into_bytes()));
+                    }
+                    // keep the NCX of EPUB 2 reading systems in step
+                    if let Some(path) = &self.ncx_path {
+                        files.push((path.clone(), ncx(path).into_bytes()));
+                    }
+                }
+                Some(TocSource::Ncx(path)) => files.push((path.clone(), ncx(path).into_bytes())),
+                None if self.legacy => {
+                    let item = self.generated_item(&manifest, "ncx", "toc.ncx");
+                    files.push((item.path.clone(), ncx(&item.path).into_bytes()));
+                    spine_toc = Some(item.id.clone());
+                    manifest.push(item);
+                }
+                None => {
+                    // an unreadable navigation document gives way to the new one
+                    for item in &mut manifest {
+                        item.properties.retain(|property| property != "nav");
+                    }
+                    let mut item = self.generated_item(&manifest, "nav", "nav.xhtml");
+                    item.properties.push(String::from("nav"));
+                    let doc = nav::nav_doc(toc, &self.metadata, &item.path);
+                    files.push((item.path.clone(), doc.into_bytes()));
+                    manifest.push(item);
+                }
+            }
+        }
+
+        let touch = toc_changed
+            || !self.resources.is_empty()
+            || self.metadata != self.original_metadata
+            || manifest != self.original_manifest.items;
+        let original = package_document::Package {
+            metadata: &self.original_metadata,
+            manifest: &self.original_manifest.items,
+            spine_toc: None,
+        };
+        let edited = package_document::Package {
+            metadata: &self.metadata,
+            manifest: &manifest,
+            spine_toc: spine_toc.as_deref(),
+        };
+        let pkg_doc = package_document::edit(
+            &self.package_document,
+            &self.package_path,
+            &original,
+            &edited,
+            touch,
+        )?;
+        if pkg_doc != self.package_document {
+            files.push((self.package_path.clone(), pkg_doc.into_bytes()));
+        }
+
+        for (path, data) in &self.resources {
+            files.retain(|(p, _)| p != path);
+            files.push((path.clone(), data.clone()));
+        }
+
+        let removed = self
+            .original_manifest
+            .iter()
+            .map(|item| &item.path)
+            .filter(|path| !manifest.iter().any(|item| &item.path == *path))
+            .filter(|path| !files.iter().any(|(p, _)| p == *path))
+            .cloned()
+            .collect();
+
+        Ok((files, removed))
+    }
+
+    /// Fill in the path, href and media type of `item`, edited from `original` if it existed.
+    fn normalize(&self, mut item: ManifestItem, original: Option<&ManifestItem>) -> ManifestItem {
+        let href_edited = original
+            .is_some_and(|original| original.href != item.href && original.path == item.path);
+        if item.path.is_empty() || href_edited {
+            item.path = path::resolve(&self.package_path, &item.href);
+        }
+        let path_changed = !matches!(original, Some(original) if original.path == item.path);
+        if item.href.is_empty() || path_changed {
+            item.href = path::relative(&self.package_path, &item.path);
+        }
+        if item.media_type.is_none() {
+            item.media_type = media_type::guess(&item.path);
+        }
+
+        item
+    }
+
+    /// manifest item for a generated document, with an id and path not taken yet
+    fn generated_item(&self, manifest: &[ManifestItem], id: &str, href: &str) -> ManifestItem {
+        let (stem, extension) = href.split_once('.').unwrap_or((href, ""));
+        let taken = |path: &str| {
+            manifest.iter().any(|item| item.path == path)
+                || self.archive.file_names().any(|name| name == path)
+        };
+        let path = (1..)
+            .map(|n| match n {
+                1 => path::resolve(&self.package_path, href),
+                n => path::resolve(&self.package_path, &format!("{}-{}.{}", stem, n, extension)),
+            })
+            .find(|path| !taken(path))
+            .unwrap_or_default();
+
+        self.normalize(
+            ManifestItem {
+                id: self.unique_id(id),
+                path,
+                ..Default::default()
+            },
+            None,
+        )
+    }
+
+    fn unique_id(&self, id: &str) -> String {
+        let taken = |id: &str| self.manifest.items.iter().any(|item| item.id == id);
+        (1..)
+            .map(|n| match n {
+                1 => id.to_string(),
+                n => format!("{}-{}", id, n),
+            })
+            .find(|id| !taken(id))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{Identifier, NavList, Parser};
+    use crate::util::test_util::{epub, with_items, CHAPTER, NAV_DOC};
+
+    const PACKAGE_DOCUMENT: &str = r##"<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" unique-identifier="uid" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <!-- kept as written -->
+    <dc:identifier id="uid">urn:uuid:12345678-1234-1234-1234-123456789abc</dc:identifier>
+    <dc:title id="title">Sample Book</dc:title>
+    <meta refines="#title" property="title-type" id="title-type">main</meta>
+    <meta refines="#title-type" property="display-seq">1</meta>
+    <dc:language>en</dc:language>
+    <meta property="dcterms:modified">2024-01-01T00:00:00Z</meta>
+    <link rel="record" href="record.xml" media-type="application/marc"/>
+  </metadata>
+  <manifest>
+    <item href="chapter-1.xhtml" id="chapter-1" media-type="application/xhtml+xml"/>
+    <item href="toc.xhtml" id="toc" media-type="application/xhtml+xml" properties="nav"/>
+  </manifest>
+  <spine>
+    <itemref idref="chapter-1"/>
+  </spine>
+</package>"##;
+
+    fn sample_epub() -> Vec<u8> {
+        epub(
+            PACKAGE_DOCUMENT,
+            &[
+                ("epub/toc.xhtml", NAV_DOC.as_bytes()),
+                ("epub/chapter-1.xhtml", CHAPTER.as_bytes()),
+            ],
+        )
+    }
+
+    #[test]
+    fn keep_untouched_book() {
+        let bytes = sample_epub();
+        let written = Parser::from_bytes(bytes.as_slice())
+            .unwrap()
+            .edit()
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+
+        let mut archive = ZipArchive::new(Cursor::new(written)).unwrap();
+        assert_eq!(
+            PACKAGE_DOCUMENT,
+            read_text_file(&mut archive, "epub/content.opf").unwrap()
+        );
+    }
+
+    #[test]
+    fn edit_metadata_in_place() {
+        let bytes = sample_epub();
+        let mut editor = Parser::from_bytes(bytes.as_slice())
+            .unwrap()
+            .edit()
+            .unwrap();
+        editor.metadata.titles[0].value = String::from("Edited Book");
+        editor.metadata.identifiers.push(Identifier {
+            value: String::from("urn:isbn:9780000000000"),
+            ..Default::default()
+        });
+        editor.toc.contents[0].title = String::from("Edited Chapter");
+        editor.add_resource(
+            ManifestItem {
+                id: String::from("chapter-2"),
+                href: String::from("chapter-2.xhtml"),
+                ..Default::default()
+            },
+            CHAPTER,
+        );
+        let written = editor.to_bytes().unwrap();
+
+        let mut original = ZipArchive::new(Cursor::new(bytes.as_slice())).unwrap();
+        let mut archive = ZipArchive::new(Cursor::new(written.as_slice())).unwrap();
+        let chapter = |archive: &mut ZipArchive<Cursor<&[u8]>>| {
+            let file = archive.by_name("epub/chapter-1.xhtml").unwrap();
+            (file.crc32(), file.compressed_size())
+        };
+        assert_eq!(chapter(&mut original).0, chapter(&mut archive).0);
+        assert_eq!(chapter(&mut original).1, chapter(&mut archive).1);
+
+        let pkg_doc = read_text_file(&mut archive, "epub/content.opf").unwrap();
+        assert!(pkg_doc.contains("<!-- kept as written -->"));
+        assert!(pkg_doc.contains(r#"<link rel="record" href="record.xml""#));
+        assert!(pkg_doc.contains(
+            r##"    <dc:title id="title">Edited Book</dc:title>
+    <meta refines="#title" property="title-type">main</meta>"##
+        ));
+        assert!(!pkg_doc.contains("2024-01-01T00:00:00Z"));
+        assert!(!pkg_doc.contains(r##"refines="#title-type""##));
+
+        let meta = Parser::from_bytes(written.as_slice())
+            .unwrap()
+            .meta()
+            .unwrap();
+        assert_eq!("Edited Book", meta.title);
+        assert_eq!(Some(1), meta.metadata.titles[0].display_seq);
+        assert_eq!(2, meta.metadata.identifiers.len());
+        assert_eq!("Edited Chapter", meta.toc.contents[0].title);
+        assert_eq!(
+            Some("epub/chapter-2.xhtml"),
+            meta.manifest
+                .by_id("chapter-2")
+                .map(|item| item.path.as_str())
+        );
+    }
+
+    #[test]
+    fn reject_conflicting_edits() {
+        let err = Parser::from_bytes(epub("<package><metadata></package>", &[]))
+            .unwrap()
+            .edit()
+            .unwrap_err();
+        assert!(matches!(err, Error::Xml { path, .. } if path == "epub/content.opf"));
+
+        let editor = || Parser::from_bytes(sample_epub()).unwrap().edit().unwrap();
+        let item = |id: &str, href: &str| ManifestItem {
+            id: id.to_string(),
+            href: href.to_string(),
+            ..Default::default()
+        };
+
+        let mut added = editor();
+        added.add_resource(item("chapter-1", "chapter-2.xhtml"), CHAPTER);
+        assert!(matches!(
+            added.to_bytes(),
+            Err(Error::DuplicateManifestItem { id }) if id == "chapter-1"
+        ));
+        let mut added = editor();
+        added.add_resource(item("chapter-2", "chapter-1.xhtml"), CHAPTER);
+        assert!(matches!(
+            added.to_bytes(),
+            Err(Error::DuplicateResource { path }) if path == "epub/chapter-1.xhtml"
+        ));
+        let mut moved = editor();
+        moved.manifest.items[0].href = String::from("toc.xhtml");
+        assert!(matches!(
+            moved.to_bytes(),
+            Err(Error::DuplicateResource { path }) if path == "epub/toc.xhtml"
+        ));
+    }
+
+    #[test]
+    fn edit_without_readable_toc() {
+        let encryption = r#"<encryption xmlns="urn:oasis:names:tc:opendocument:xmlns:container"
+            xmlns:enc="http://www.w3.org/2001/04/xmlenc#">
+    <enc:EncryptedData>
+        <enc:EncryptionMethod Algorithm="http://www.w3.org/2001/04/xmlenc#aes256-cbc"/>
+        <enc:CipherData><enc:CipherReference URI="epub/toc.xhtml"/></enc:CipherData>
+    </enc:EncryptedData>
+</encryption>"#;
+        let bytes = epub(
+            PACKAGE_DOCUMENT,
+            &[
+                ("META-INF/encryption.xml", encryption.as_bytes()),
+                ("epub/toc.xhtml", NAV_DOC.as_bytes()),
+                ("epub/chapter-1.xhtml", CHAPTER.as_bytes()),
+            ],
+        );
+        let mut editor = Parser::from_bytes(bytes.as_slice())
+            .unwrap()
+            .edit()
+            .unwrap();
+        assert_eq!(Toc::default(), editor.toc);
+
+        // nav lists are read-only
+        editor.toc.nav_lists.push(NavList {
+            title: String::from("Illustrations"),
+            ..Default::default()
+        });
+        let written = editor.to_bytes().unwrap();
+        let mut archive = ZipArchive::new(Cursor::new(written)).unwrap();
+        assert_eq!(
+            PACKAGE_DOCUMENT,
+            read_text_file(&mut archive, "epub/content.opf").unwrap()
+        );
+        assert_eq!(
+            NAV_DOC,
+            read_text_file(&mut archive, "epub/toc.xhtml").unwrap()
+        );
+
+        editor.metadata.titles[0].value = String::from("Edited Book");
+        let written = editor.to_bytes().unwrap();
+        let mut archive = ZipArchive::new(Cursor::new(written)).unwrap();
+        assert!(read_text_file(&mut archive, "epub/content.opf")
+            .unwrap()
+            .contains("Edited Book"));
+    }
+
+    #[test]
+    fn keep_previous_cover_image() {
+        let pkg_doc = with_items(
+            PACKAGE_DOCUMENT,
+            &[
+                r#"<item href="cover.xhtml" id="cover" media-type="application/xhtml+xml"/>"#,
+                r#"<item href="images/cover.jpg" id="cover-jpg" media-type="image/jpeg" properties="cover-image"/>"#,
+            ],
+        );
+        let cover_page = r#"<html xmlns="http://www.w3.org/1999/xhtml">
+<body><img src="images/cover.jpg" alt="Cover"/></body>
+</html>"#;
+        let bytes = epub(
+            &pkg_doc,
+            &[
+                ("epub/toc.xhtml", NAV_DOC.as_bytes()),
+                ("epub/cover.xhtml", cover_page.as_bytes()),
+                ("epub/images/cover.jpg", b"jpeg"),
+                ("epub/chapter-1.xhtml", CHAPTER.as_bytes()),
+            ],
+        );
+        let mut editor = Parser::from_bytes(bytes.as_slice())
+            .unwrap()
+            .edit()
+            .unwrap();
+        editor.set_cover("epub/images/cover-2.png", *b"png");
+        let written = editor.to_bytes().unwrap();
+
+        let mut parser = Parser::from_bytes(written.as_slice()).unwrap();
+        let cover = parser.cover().unwrap().unwrap();
+        assert_eq!("epub/images/cover-2.png", cover.path);
+        assert_eq!(b"png".to_vec(), cover.data);
+        assert_eq!(
+            b"jpeg".to_vec(),
+            parser
+                .resource_by_href("epub/cover.xhtml", "images/cover.jpg")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn edit_toc_where_it_was_read() {
+        let ncx = r#"<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/"><navMap>
+    <navPoint id="ch1"><navLabel><text>Chapter 1</text></navLabel><content src="chapter-1.xhtml"/></navPoint>
+</navMap></ncx>"#;
+        let pkg_doc = with_items(
+            PACKAGE_DOCUMENT,
+            &[r#"<item href="toc.ncx" id="ncx" media-type="application/x-dtbncx+xml"/>"#],
+        )
+        .replace("<spine>", r#"<spine toc="ncx">"#);
+        let bytes = epub(
+            &pkg_doc,
+            &[
+                ("epub/toc.xhtml", b"<html><body>"),
+                ("epub/toc.ncx", ncx.as_bytes()),
+                ("epub/chapter-1.xhtml", CHAPTER.as_bytes()),
+            ],
+        );
+        let mut editor = Parser::from_bytes(bytes.as_slice())
+            .unwrap()
+            .edit()
+            .unwrap();
+        editor.toc.contents[0].title = String::from("Edited Chapter");
+        let written = editor.to_bytes().unwrap();
+
+        // the broken navigation document is left alone, the NCX is rewritten
+        let mut archive = ZipArchive::new(Cursor::new(written.as_slice())).unwrap();
+        assert_eq!(
+            "<html><body>",
+            read_text_file(&mut archive, "epub/toc.xhtml").unwrap()
+        );
+        let meta = Parser::from_bytes(written).unwrap().meta().unwrap();
+        assert_eq!("Edited Chapter", meta.toc.contents[0].title);
+
+        // an EPUB 2 book without a table of contents gets an NCX, and no EPUB 3 `properties`
+        let pkg_doc = PACKAGE_DOCUMENT
+            .replace(r#"version="3.0""#, r#"version="2.0""#)
+            .replace(
+                r#"    <item href="toc.xhtml" id="toc" media-type="application/xhtml+xml" properties="nav"/>
+"#,
+                "",
+            );
+        let bytes = epub(&pkg_doc, &[("epub/chapter-1.xhtml", CHAPTER.as_bytes())]);
+        let mut editor = Parser::from_bytes(bytes.as_slice())
+            .unwrap()
+            .edit()
+            .unwrap();
+        assert!(editor.toc.synthesized);
+        editor.toc.contents[0].title = String::from("Edited Chapter");
+        editor.set_cover("epub/cover.png", *b"png");
+        let written = editor.to_bytes().unwrap();
+
+        let mut archive = ZipArchive::new(Cursor::new(written.as_slice())).unwrap();
+        let edited_pkg_doc = read_text_file(&mut archive, "epub/content.opf").unwrap();
+        assert!(!edited_pkg_doc.contains("properties="));
+        assert!(edited_pkg_doc.contains(r#"<spine toc="ncx">"#));
+        let meta = Parser::from_bytes(written).unwrap().meta().unwrap();
+        assert!(!meta.toc.synthesized);
+        assert_eq!("Edited Chapter", meta.toc.contents[0].title);
+        assert_eq!(Some("epub/cover.png"), meta.cover.as_deref());
+    }
+}
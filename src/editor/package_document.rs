@@ -0,0 +1,314 @@
+use roxmltree::Node;
+
+use crate::builder::package_document::{
+    dc_elements, manifest_item, meta_element, modified_element, timestamp, Ids, MetadataStyle,
+    Prefix, DC_ELEMENTS, NAMESPACE_OPF, REFINEMENTS,
+};
+use crate::editor::splice::Splices;
+use crate::error::{Error, Result};
+use crate::parser::{ManifestItem, Meta, Metadata};
+use crate::util::xml_util;
+
+const NAMESPACE_DC: &str = "http://purl.org/dc/elements/1.1/";
+
+/// metadata and manifest of a package document
+pub(super) struct Package<'a> {
+    pub metadata: &'a Metadata,
+    pub manifest: &'a [ManifestItem],
+    /// manifest id of the NCX the spine `toc` attribute should point to
+    pub spine_toc: Option<&'a str>,
+}
+
+/// Apply the differences between `original` and `edited` onto the package document `source`.
+/// Elements without changes are left as written. `touch` updates `dcterms:modified` of EPUB 3
+/// package documents unless it was edited already.
+pub(super) fn edit(
+    source: &str,
+    path: &str,
+    original: &Package,
+    edited: &Package,
+    touch: bool,
+) -> Result<String> {
+    let doc = xml_util::parse(source, path)?;
+    let package_elem = doc.root_element();
+    let child = |name: &'static str| {
+        package_elem
+            .children()
+            .find(|node| node.has_tag_name(name))
+            .ok_or_else(|| Error::missing_element(path, name))
+    };
+    let metadata_elem = child("metadata")?;
+    let manifest_elem = child("manifest")?;
+    let spine_elem = child("spine")?;
+
+    let legacy = is_legacy(&package_elem);
+    let mut metadata = edited.metadata.clone();
+    if touch && !legacy && metadata.modified == original.metadata.modified {
+        metadata.modified = Some(timestamp());
+    }
+
+    let mut splices = Splices::new(source);
+    let style = MetadataStyle {
+        dc: prefix(&metadata_elem, NAMESPACE_DC, "dc"),
+        opf: legacy.then(|| prefix(&metadata_elem, NAMESPACE_OPF, "opf")),
+    };
+    edit_metadata(
+        &mut splices,
+        &metadata_elem,
+        &style,
+        original.metadata,
+        &metadata,
+    );
+    edit_manifest(
+        &mut splices,
+        &manifest_elem,
+        &spine_elem,
+        original.manifest,
+        edited.manifest,
+        legacy,
+    );
+    if let Some(id) = edited.spine_toc {
+        if spine_elem.attribute("toc").map(|toc| toc.trim()) != Some(id) {
+            splices.set_attribute(&spine_elem, "toc", id);
+        }
+    }
+
+    Ok(splices.apply())
+}
+
+fn edit_metadata(
+    splices: &mut Splices,
+    metadata_elem: &Node,
+    style: &MetadataStyle,
+    original: &Metadata,
+    metadata: &Metadata,
+) {
+    let children: Vec<Node> = metadata_elem
+        .children()
+        .filter(|node| node.is_element())
+        .collect();
+    let metas: Vec<&Node> = children
+        .iter()
+        .filter(|node| node.has_tag_name((NAMESPACE_OPF, "meta")))
+        .collect();
+    let mut ids = Ids::new(metadata);
+
+    // elements of a changed kind are rewritten in place of the first one, with their refinements
+    for name in DC_ELEMENTS {
+        if !dc_changed(original, metadata, name) {
+            continue;
+        }
+
+        let nodes: Vec<&Node> = children
+            .iter()
+            .filter(|node| {
+                node.tag_name().namespace() == Some(NAMESPACE_DC) && node.tag_name().name() == name
+            })
+            .collect();
+        let node_ids: Vec<&str> = nodes
+            .iter()
+            .filter_map(|node| node.attribute("id"))
+            .map(|id| id.trim())
+            .collect();
+        for meta in &metas {
+            let refines = meta
+                .attribute("refines")
+                .map(|refines| refines.trim().trim_start_matches('#'));
+            let property = meta.attribute("property").unwrap_or_default().trim();
+            // refinements of those refinements go with them
+            if refines.is_some_and(|refines| node_ids.contains(&original.refined_id(refines)))
+                && REFINEMENTS.contains(&property)
+            {
+                splices.remove(meta);
+            }
+        }
+
+        let (elements, refinements) = dc_elements(metadata, name, style, &mut ids);
+        let lines: Vec<String> = elements.into_iter().chain(refinements).collect();
+        match nodes.split_first() {
+            Some((first, rest)) => {
+                if lines.is_empty() {
+                    splices.remove(first);
+                } else {
+                    let indent = splices.indent(first);
+                    splices.replace(first, lines.join(&format!("\n{}", indent)));
+                }
+                for node in rest {
+                    splices.remove(node);
+                }
+            }
+            None => {
+                for line in &lines {
+                    splices.append(metadata_elem, line);
+                }
+            }
+        }
+    }
+
+    if original.modified != metadata.modified {
+        let node = metas.iter().find(|node| {
+            node.attribute("refines").is_none()
+                && node.attribute("property").map(|property| property.trim())
+                    == Some("dcterms:modified")
+        });
+        match (node, &metadata.modified) {
+            (Some(node), Some(modified)) => splices.replace(node, modified_element(modified)),
+            (Some(node), None) => splices.remove(node),
+            (None, Some(modified)) => splices.append(metadata_elem, &modified_element(modified)),
+            (None, None) => {}
+        }
+    }
+
+    // metas compare as a multiset, the edited ones may be in any order
+    let is_modified = |meta: &Meta| meta.refines.is_none() && meta.property == "dcterms:modified";
+    let mut added: Vec<&Meta> = metadata
+        .metas
+        .iter()
+        .filter(|meta| !is_modified(meta))
+        .collect();
+    let mut removed = vec![];
+    for meta in original.metas.iter().filter(|meta| !is_modified(meta)) {
+        match added.iter().position(|added| *added == meta) {
+            Some(i) => {
+                added.remove(i);
+            }
+            None => removed.push(meta),
+        }
+    }
+    let mut matched = vec![];
+    for meta in removed {
+        let node = metas
+            .iter()
+            .enumerate()
+            .find(|(i, node)| !matched.contains(i) && matches_meta(node, meta));
+        if let Some((i, node)) = node {
+            splices.remove(node);
+            matched.push(i);
+        }
+    }
+    for meta in added {
+        splices.append(metadata_elem, &meta_element(meta));
+    }
+}
+
+fn edit_manifest(
+    splices: &mut Splices,
+    manifest_elem: &Node,
+    spine_elem: &Node,
+    original: &[ManifestItem],
+    manifest: &[ManifestItem],
+    legacy: bool,
+) {
+    // `properties` came with EPUB 3
+    let manifest_item = |item: &ManifestItem| match legacy {
+        true => manifest_item(&ManifestItem {
+            properties: vec![],
+            ..item.clone()
+        }),
+        false => manifest_item(item),
+    };
+
+    for item in original {
+        let nodes = by_id(manifest_elem, "item", "id", &item.id);
+        match manifest.iter().find(|edited| edited.id == item.id) {
+            None => {
+                for node in &nodes {
+                    splices.remove(node);
+                }
+                for itemref in by_id(spine_elem, "itemref", "idref", &item.id) {
+                    splices.remove(&itemref);
+                }
+            }
+            Some(edited) if edited != item => {
+                if let Some(node) = nodes.first() {
+                    splices.replace(node, manifest_item(edited));
+                }
+            }
+            Some(_) => {}
+        }
+    }
+
+    for item in manifest
+        .iter()
+        .filter(|item| !original.iter().any(|original| original.id == item.id))
+    {
+        splices.append(manifest_elem, &manifest_item(item));
+    }
+}
+
+/// whether `package_elem` is the root of an EPUB 2 package document
+pub(super) fn is_legacy(package_elem: &Node) -> bool {
+    package_elem
+        .attribute("version")
+        .and_then(|version| version.trim().split('.').next()?.parse::<u32>().ok())
+        .is_some_and(|major| major < 3)
+}
+
+/// children of `parent` named `tag_name` with `attribute` set to `id`
+fn by_id<'a, 'input>(
+    parent: &Node<'a, 'input>,
+    tag_name: &str,
+    attribute: &str,
+    id: &str,
+) -> Vec<Node<'a, 'input>> {
+    parent
+        .children()
+        .filter(|node| node.has_tag_name(tag_name))
+        .filter(|node| node.attribute(attribute).map(|str| str.trim()) == Some(id))
+        .collect()
+}
+
+fn dc_changed(original: &Metadata, metadata: &Metadata, name: &str) -> bool {
+    match name {
+        "identifier" => original.identifiers != metadata.identifiers,
+        "title" => original.titles != metadata.titles,
+        "language" => original.languages != metadata.languages,
+        "creator" => original.creators != metadata.creators,
+        "contributor" => original.contributors != metadata.contributors,
+        "publisher" => original.publishers != metadata.publishers,
+        "date" => original.dates != metadata.dates,
+        "description" => original.descriptions != metadata.descriptions,
+        "subject" => original.subjects != metadata.subjects,
+        "rights" => original.rights != metadata.rights,
+        "source" => original.sources != metadata.sources,
+        "type" => original.types != metadata.types,
+        "format" => original.formats != metadata.formats,
+        "relation" => original.relations != metadata.relations,
+        "coverage" => original.coverages != metadata.coverages,
+        _ => false,
+    }
+}
+
+/// whether the `meta` element `node` was parsed into `meta`
+fn matches_meta(node: &Node, meta: &Meta) -> bool {
+    let trim = |str: Option<&str>| str.map(|str| str.trim().to_string());
+    let (property, value) = match node.attribute("property") {
+        Some(property) => (Some(property), node.text()),
+        None => (node.attribute("name"), node.attribute("content")),
+    };
+
+    trim(property).as_ref() == Some(&meta.property)
+        && trim(value).unwrap_or_default() == meta.value
+        && trim(node.attribute("refines"))
+            .map(|refines| refines.trim_start_matches('#').to_string())
+            == meta.refines
+}
+
+/// prefix `uri` is bound to at `node`, declared on each element as `default` if unbound
+fn prefix(node: &Node, uri: &str, default: &str) -> Prefix {
+    let name = node
+        .namespaces()
+        .filter(|namespace| namespace.uri() == uri)
+        .find_map(|namespace| namespace.name());
+
+    match name {
+        Some(name) => Prefix {
+            name: name.to_string(),
+            declare: false,
+        },
+        None => Prefix {
+            name: default.to_string(),
+            declare: true,
+        },
+    }
+}
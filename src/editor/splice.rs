@@ -0,0 +1,130 @@
+use std::ops::Range;
+
+use roxmltree::Node;
+
+use crate::util::xml_util::escape;
+
+/// Text edits on an XML document, addressed by the byte ranges of its parsed nodes. Everything
+/// outside the edited ranges is kept as written.
+pub(crate) struct Splices<'a> {
+    source: &'a str,
+    edits: Vec<(Range<usize>, String)>,
+}
+
+impl<'a> Splices<'a> {
+    pub fn new(source: &'a str) -> Splices<'a> {
+        Splices {
+            source,
+            edits: vec![],
+        }
+    }
+
+    /// Replace `node`, keeping the whitespace around it.
+    pub fn replace(&mut self, node: &Node, text: String) {
+        self.edits.push((node.range(), text));
+    }
+
+    /// Remove `node` along with the line it was on, if it had the line to itself.
+    pub fn remove(&mut self, node: &Node) {
+        let range = node.range();
+        let line_start = self.source[..range.start]
+            .rfind('\n')
+            .unwrap_or(range.start);
+        let start = if self.source[line_start..range.start]
+            .chars()
+            .all(char::is_whitespace)
+        {
+            line_start
+        } else {
+            range.start
+        };
+
+        self.edits.push((start..range.end, String::new()));
+    }
+
+    /// Insert `text` as the last child element of `parent`, indented like its siblings.
+    pub fn append(&mut self, parent: &Node, text: &str) {
+        match parent.children().rfind(|node| node.is_element()) {
+            Some(last) => {
+                let position = last.range().end;
+                let text = format!("\n{}{}", self.indent(&last), text);
+                self.edits.push((position..position, text));
+            }
+            None => {
+                let range = parent.range();
+                let position = self.source[..range.end]
+                    .rfind("</")
+                    .filter(|position| *position > range.start)
+                    .unwrap_or(range.end);
+                let indent = self.indent(parent);
+                let text = format!("\n{}    {}\n{}", indent, text, indent);
+                self.edits.push((position..position, text));
+            }
+        }
+    }
+
+    /// Set the attribute `name` of the element `node` to `value`, in place of its current value
+    /// or after the tag name.
+    pub fn set_attribute(&mut self, node: &Node, name: &str, value: &str) {
+        let text = format!(r#"{}="{}""#, name, escape(value));
+        let attribute = node
+            .attributes()
+            .find(|attribute| attribute.namespace().is_none() && attribute.name() == name);
+        match attribute {
+            Some(attribute) => {
+                // the value runs from the quote after `=` to the same quote
+                let start = attribute.position();
+                let end = self.source[start..]
+                    .find(['"', '\''])
+                    .and_then(|open| {
+                        let quote = &self.source[start + open..start + open + 1];
+                        let close = self.source[start + open + 1..].find(quote)?;
+                        Some(start + open + 1 + close + 1)
+                    })
+                    .unwrap_or(start);
+                self.edits.push((start..end, text));
+            }
+            None => {
+                let start = node.range().start;
+                let position = self.source[start..]
+                    .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+                    .map_or(start, |end| start + end);
+                self.edits.push((position..position, format!(" {}", text)));
+            }
+        }
+    }
+
+    /// whitespace `node` is indented with
+    pub fn indent(&self, node: &Node) -> &'a str {
+        let start = node.range().start;
+        let line_start = self.source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let indent = &self.source[line_start..start];
+
+        if indent.chars().all(char::is_whitespace) {
+            indent
+        } else {
+            ""
+        }
+    }
+
+    /// Apply the edits in document order. Edits overlapping an earlier one are dropped.
+    pub fn apply(mut self) -> String {
+        // insertions go before a removal starting at the same position
+        self.edits
+            .sort_by_key(|(range, _)| (range.start, range.end));
+
+        let mut result = String::with_capacity(self.source.len());
+        let mut cursor = 0;
+        for (range, text) in self.edits {
+            if range.start < cursor {
+                continue;
+            }
+            result.push_str(&self.source[cursor..range.start]);
+            result.push_str(&text);
+            cursor = range.end;
+        }
+        result.push_str(&self.source[cursor..]);
+
+        result
+    }
+}
@@ -0,0 +1,82 @@
+use roxmltree::Node;
+
+use crate::builder::nav;
+use crate::editor::splice::Splices;
+use crate::error::Result;
+use crate::parser::Toc;
+use crate::util::xml_util;
+
+const NAMESPACE_OPS: &str = "http://www.idpf.org/2007/ops";
+
+/// serializes a `hidden` nav, `None` when it would be empty
+type HiddenNav = fn(&Toc, &str, &str) -> Option<String>;
+
+/// Apply the differences between `original` and `toc` onto the navigation document `source`.
+/// The lists are rewritten; the `nav` elements, their headings and the rest of the document are
+/// left as written.
+pub(super) fn edit_nav_doc(source: &str, path: &str, original: &Toc, toc: &Toc) -> Result<String> {
+    let doc = xml_util::parse(source, path)?;
+    let navs: Vec<Node> = doc
+        .descendants()
+        .filter(|node| node.has_tag_name("nav"))
+        .collect();
+    let find = |epub_type: &str| navs.iter().find(|nav| has_epub_type(nav, epub_type));
+    let toc_nav =
+        find("toc").or_else(|| navs.iter().find(|nav| nav.attribute("id") == Some("toc")));
+
+    let mut splices = Splices::new(source);
+    if let (Some(toc_nav), true) = (toc_nav, original.contents != toc.contents) {
+        match toc_nav.children().find(|node| node.has_tag_name("ol")) {
+            Some(list) => {
+                let indent = splices.indent(&list);
+                splices.replace(&list, nav::list(&toc.contents, path, indent));
+            }
+            None => {
+                let indent = splices.indent(toc_nav);
+                splices.replace(toc_nav, nav::toc_nav(toc, None, path, indent));
+            }
+        }
+    }
+
+    let hidden_navs: [(&str, bool, HiddenNav); 2] = [
+        (
+            "landmarks",
+            original.landmarks != toc.landmarks,
+            nav::landmarks_nav,
+        ),
+        (
+            "page-list",
+            original.page_list != toc.page_list,
+            nav::page_list_nav,
+        ),
+    ];
+    for (epub_type, changed, generate) in hidden_navs {
+        if !changed {
+            continue;
+        }
+        match find(epub_type) {
+            Some(node) => match generate(toc, path, splices.indent(node)) {
+                Some(text) => splices.replace(node, text),
+                None => splices.remove(node),
+            },
+            None => {
+                let parent = toc_nav
+                    .and_then(|nav| nav.parent_element())
+                    .or_else(|| doc.descendants().find(|node| node.has_tag_name("body")));
+                if let Some(parent) = parent {
+                    let indent = toc_nav.map_or("", |nav| splices.indent(nav));
+                    if let Some(text) = generate(toc, path, indent) {
+                        splices.append(&parent, &text);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(splices.apply())
+}
+
+fn has_epub_type(node: &Node, epub_type: &str) -> bool {
+    node.attribute((NAMESPACE_OPS, "type"))
+        .is_some_and(|types| types.split_ascii_whitespace().any(|t| t == epub_type))
+}
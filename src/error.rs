@@ -0,0 +1,112 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::sync::Arc;
+
+use zip::result::ZipError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// the EPUB file could not be opened
+    Io(io::Error),
+    /// the EPUB is not a readable zip archive
+    Archive(ZipError),
+    /// the resource exists but could not be read, e.g. a corrupt entry
+    Resource { path: String, source: ZipError },
+    /// the resource does not exist in the archive
+    MissingResource { path: String },
+    /// the resource is not well-formed XML
+    Xml {
+        path: String,
+        source: roxmltree::Error,
+    },
+    /// `container.xml` does not declare any `rootfile`
+    NoRootFile,
+    /// the package document declared in `container.xml` does not exist
+    MissingPackageDocument { path: String },
+    /// a required element is missing from an XML document
+    MissingElement { path: String, element: &'static str },
+    /// the search query is not a valid regular expression
+    InvalidQuery(regex::Error),
+    /// `cfi` is not a valid EPUB CFI, `position` is the byte offset of the error
+    InvalidCfi { cfi: String, position: usize },
+    /// the resource is encrypted, e.g. by DRM, with the `algorithm` of `META-INF/encryption.xml`
+    EncryptedResource { path: String, algorithm: String },
+    /// the manifest has no item with this `id`
+    MissingManifestItem { id: String },
+    /// the spine has no item at this `index`
+    MissingSpineItem { index: usize },
+    /// more than one manifest item has this `id`
+    DuplicateManifestItem { id: String },
+    /// more than one resource is written at this `path`
+    DuplicateResource { path: String },
+    /// an earlier failure kept by the parser, e.g. of `container.xml` or the package document,
+    /// returned again by every lookup depending on it
+    Cached(Arc<Error>),
+}
+
+impl Error {
+    pub(crate) fn missing_element(path: &str, element: &'static str) -> Error {
+        Error::MissingElement {
+            path: path.to_string(),
+            element,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "failed to open EPUB: {}", err),
+            Error::Archive(err) => write!(f, "invalid zip archive: {}", err),
+            Error::Resource { path, source } => {
+                write!(f, "failed to read `{}`: {}", path, source)
+            }
+            Error::MissingResource { path } => write!(f, "`{}` not found", path),
+            Error::Xml { path, source } => write!(f, "malformed XML in `{}`: {}", path, source),
+            Error::NoRootFile => write!(f, "no `rootfile` found"),
+            Error::MissingPackageDocument { path } => {
+                write!(f, "package document `{}` not found", path)
+            }
+            Error::MissingElement { path, element } => {
+                write!(f, "`{}` node not found in `{}`", element, path)
+            }
+            Error::InvalidQuery(err) => write!(f, "invalid search query: {}", err),
+            Error::InvalidCfi { cfi, position } => {
+                write!(f, "invalid CFI `{}` at position {}", cfi, position)
+            }
+            Error::EncryptedResource { path, algorithm } => {
+                write!(f, "`{}` is encrypted with `{}`", path, algorithm)
+            }
+            Error::MissingManifestItem { id } => write!(f, "no manifest item with id `{}`", id),
+            Error::MissingSpineItem { index } => write!(f, "no spine item at index {}", index),
+            Error::DuplicateManifestItem { id } => {
+                write!(f, "more than one manifest item with id `{}`", id)
+            }
+            Error::DuplicateResource { path } => write!(f, "more than one resource at `{}`", path),
+            Error::Cached(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Archive(err) => Some(err),
+            Error::Resource { source, .. } => Some(source),
+            Error::Xml { source, .. } => Some(source),
+            Error::InvalidQuery(err) => Some(err),
+            Error::Cached(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
@@ -1,2 +1,9 @@
+pub use crate::error::{Error, Result};
+
+pub mod builder;
+pub mod cfi;
+pub mod editor;
+mod error;
 pub mod parser;
-mod util;
\ No newline at end of file
+mod util;
+pub mod validator;
@@ -0,0 +1,222 @@
+use std::fs::File;
+use std::io;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+use zip::ZipArchive;
+
+use crate::error::{Error, Result};
+use crate::parser::encryption::Encryption;
+use crate::parser::{BookMeta, Href, Parser};
+use crate::util::zip_util::read_binary_file;
+
+/// A parsed book whose resources can be read from several threads at once, e.g. shared by an
+/// `Arc<Book>` between the requests of a web server.
+///
+/// Every read works on its own clone of the archive reader, sharing the parsed zip directory.
+/// See `Book::open` and `Book::from_bytes`, or `Parser::into_book` for any other cloneable reader.
+#[derive(Debug)]
+pub struct Book<R: Read + Seek + Clone = SharedFile> {
+    archive: ZipArchive<R>,
+    meta: BookMeta,
+    encryption: Encryption,
+}
+
+/// A file read with positional reads. Clones share the file handle but not the position.
+#[derive(Debug, Clone)]
+pub struct SharedFile {
+    file: Arc<File>,
+    len: u64,
+    position: u64,
+}
+
+impl Book<SharedFile> {
+    /// Open the EPUB at `path`. Reads share one file handle.
+    pub fn open(path: &str) -> Result<Book<SharedFile>> {
+        let file = File::open(path)?;
+
+        Parser::from_reader(SharedFile::new(file)?)?.into_book()
+    }
+}
+
+impl Book<Cursor<Arc<[u8]>>> {
+    /// Parse an EPUB held in memory.
+    pub fn from_bytes(bytes: impl Into<Arc<[u8]>>) -> Result<Book<Cursor<Arc<[u8]>>>> {
+        Parser::from_reader(Cursor::new(bytes.into()))?.into_book()
+    }
+}
+
+impl<R: Read + Seek + Clone> Book<R> {
+    pub(crate) fn new(archive: ZipArchive<R>, meta: BookMeta, encryption: Encryption) -> Book<R> {
+        Book {
+            archive,
+            meta,
+            encryption,
+        }
+    }
+
+    /// Metadata, manifest, spine and table of contents, as `Parser::meta` returns them.
+    pub fn meta(&self) -> &BookMeta {
+        &self.meta
+    }
+
+    /// Read the resource at `path`, see `Parser::resource`.
+    pub fn resource(&self, path: &str) -> Result<Vec<u8>> {
+        self.encryption.check(path)?;
+
+        let mut data = self.raw_resource(path)?;
+        if let Some(obfuscation) = self.encryption.obfuscation(path) {
+            obfuscation.deobfuscate(&mut data, &self.meta.metadata);
+        }
+
+        Ok(data)
+    }
+
+    /// Read the resource at `path` as stored in the archive.
+    pub fn raw_resource(&self, path: &str) -> Result<Vec<u8>> {
+        read_binary_file(&mut self.archive.clone(), path)
+    }
+
+    /// Read the resource of the manifest item `id`.
+    pub fn resource_by_id(&self, id: &str) -> Result<Vec<u8>> {
+        let item = self
+            .meta
+            .manifest
+            .by_id(id)
+            .ok_or_else(|| Error::MissingManifestItem { id: id.to_string() })?;
+
+        self.resource(&item.path)
+    }
+
+    /// Read the resource `href` refers to from the document at `doc_path`. The fragment, if
+    /// any, is ignored.
+    pub fn resource_by_href(&self, doc_path: &str, href: &str) -> Result<Vec<u8>> {
+        let href = Href::resolve(doc_path, href);
+
+        self.resource(&href.path)
+    }
+
+    /// Read the spine item at `index`, in reading order.
+    pub fn spine_resource(&self, index: usize) -> Result<Vec<u8>> {
+        let item = self
+            .meta
+            .spine
+            .items
+            .get(index)
+            .ok_or(Error::MissingSpineItem { index })?;
+
+        self.resource(&item.path)
+    }
+}
+
+impl SharedFile {
+    pub fn new(file: File) -> io::Result<SharedFile> {
+        let len = file.metadata()?.len();
+
+        Ok(SharedFile {
+            file: Arc::new(file),
+            len,
+            position: 0,
+        })
+    }
+}
+
+impl Read for SharedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = read_at(&self.file, buf, self.position)?;
+        self.position += read as u64;
+
+        Ok(read)
+    }
+}
+
+impl Seek for SharedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek before the start of the file",
+            )
+        })?;
+
+        Ok(self.position)
+    }
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn read_at(_file: &File, _buf: &mut [u8], _offset: u64) -> io::Result<usize> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::util::test_util::{build_zip, sample_epub, CHAPTER, CONTAINER};
+
+    #[test]
+    fn read_from_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Book<SharedFile>>();
+        assert_send_sync::<Book<Cursor<Arc<[u8]>>>>();
+
+        // removes the file once the book is dropped, even when an assertion fails
+        struct TempFile(std::path::PathBuf);
+        impl Drop for TempFile {
+            fn drop(&mut self) {
+                let _ = std::fs::remove_file(&self.0);
+            }
+        }
+
+        let file =
+            TempFile(std::env::temp_dir().join(format!("ezpub-book-{}.epub", std::process::id())));
+        std::fs::write(&file.0, sample_epub()).unwrap();
+        let file_book = Book::open(file.0.to_str().unwrap()).unwrap();
+        let memory_book = Book::from_bytes(sample_epub()).unwrap();
+        assert_eq!(file_book.meta(), memory_book.meta());
+
+        let file_book = Arc::new(file_book);
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let book = Arc::clone(&file_book);
+                thread::spawn(move || match i % 2 {
+                    0 => book.spine_resource(0).unwrap(),
+                    _ => book.resource_by_id("chapter-1").unwrap(),
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(CHAPTER.as_bytes(), handle.join().unwrap().as_slice());
+        }
+
+        assert!(matches!(
+            memory_book.resource_by_id("missing"),
+            Err(Error::MissingManifestItem { id }) if id == "missing"
+        ));
+        assert!(matches!(
+            memory_book.spine_resource(1),
+            Err(Error::MissingSpineItem { index: 1 })
+        ));
+        let bytes = build_zip(&[("META-INF/container.xml", CONTAINER.as_bytes())]);
+        assert!(matches!(
+            Book::from_bytes(bytes),
+            Err(Error::MissingPackageDocument { path }) if path == "epub/content.opf"
+        ));
+    }
+}
@@ -1,44 +1,156 @@
-use std::path::PathBuf;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-use anyhow::Result;
-use roxmltree::Document;
+use crate::error::Result;
+use crate::parser::CONTAINER_PATH;
+use crate::util::{path, xml_util};
+
+const NAMESPACE_RENDITION: &str = "http://www.idpf.org/2013/rendition";
+const MEDIA_TYPE_PACKAGE_DOCUMENT: &str = "application/oebps-package+xml";
 
 #[derive(Debug, PartialEq)]
 pub struct Container {
     pub root_files: Vec<RootFile>,
 }
 
-#[derive(Debug, PartialEq)]
+/// `rootfile` of `container.xml`: one rendition of the publication
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RootFile {
-    pub base_path: String,
+    /// package document path
     pub full_path: String,
+    pub media_type: Option<String>,
+    /// `rendition:language`
+    pub language: Option<String>,
+    /// `rendition:accessMode`, e.g. `textual` or `visual`
+    pub access_modes: Vec<String>,
+    /// `rendition:layout`, `reflowable` or `pre-paginated`
+    pub layout: Option<String>,
+    /// `rendition:media`, a CSS media query
+    pub media: Option<String>,
+    /// `rendition:label`
+    pub label: Option<String>,
+}
+
+/// Reading system preferences to pick a rendition with, see [`Container::select`]
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct RenditionSelector {
+    /// BCP 47 language tag, `ja` matches a rendition in `ja-JP`
+    pub language: Option<String>,
+    /// `auditory`, `tactile`, `textual` or `visual`
+    pub access_mode: Option<String>,
+    /// `reflowable` or `pre-paginated`
+    pub layout: Option<String>,
+    /// matched verbatim against `rendition:media`, media queries are not evaluated
+    pub media: Option<String>,
 }
 
 impl Container {
     pub fn from(doc: &str) -> Result<Container> {
-        let doc = Document::parse(doc)?;
+        let doc = xml_util::parse(doc, CONTAINER_PATH)?;
         let root_files: Vec<RootFile> = doc
             .descendants()
+            .filter(|node| node.has_tag_name("rootfile"))
             .filter_map(|node| {
-                if node.has_tag_name("rootfile") && node.has_attribute("full-path") {
-                    let full_path = node.attribute("full-path").unwrap().to_string();
-                    let mut path_buf = PathBuf::from(&full_path);
-                    path_buf.pop();
-                    let base_path = path_buf.to_str().unwrap().to_string();
-                    Some(RootFile {
-                        base_path,
-                        full_path,
-                    })
-                } else {
-                    None
-                }
+                let attribute = |name: &str| {
+                    node.attribute((NAMESPACE_RENDITION, name))
+                        .map(|str| str.trim().to_string())
+                };
+                Some(RootFile {
+                    // `full-path` is relative to the root of the container
+                    full_path: path::resolve("", node.attribute("full-path")?),
+                    media_type: node.attribute("media-type").map(|str| str.to_string()),
+                    language: attribute("language"),
+                    access_modes: attribute("accessMode")
+                        .map(|modes| {
+                            modes
+                                .split_ascii_whitespace()
+                                .map(|str| str.to_string())
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                    layout: attribute("layout"),
+                    media: attribute("media"),
+                    label: attribute("label"),
+                })
             })
             .collect();
 
-        return Ok(Container { root_files });
+        Ok(Container { root_files })
+    }
+
+    /// renditions with a package document, the first one being the default rendition
+    pub fn renditions(&self) -> impl Iterator<Item = &RootFile> {
+        self.root_files.iter().filter(|root_file| {
+            matches!(
+                root_file.media_type.as_deref(),
+                None | Some(MEDIA_TYPE_PACKAGE_DOCUMENT)
+            )
+        })
+    }
+
+    /// Pick the rendition matching the most preferences, the first one in document order on a
+    /// tie. A rendition matches when none of its selection attributes contradicts `selector`;
+    /// the default rendition is returned when no rendition matches.
+    pub fn select(&self, selector: &RenditionSelector) -> Option<&RootFile> {
+        let mut selected: Option<(&RootFile, usize)> = None;
+        for root_file in self.renditions() {
+            if let Some(score) = selector.score(root_file) {
+                if !matches!(selected, Some((_, best_score)) if best_score >= score) {
+                    selected = Some((root_file, score));
+                }
+            }
+        }
+
+        selected
+            .map(|(root_file, _)| root_file)
+            .or_else(|| self.renditions().next())
+    }
+}
+
+impl RenditionSelector {
+    /// number of preferences `root_file` satisfies, `None` if it contradicts one
+    fn score(&self, root_file: &RootFile) -> Option<usize> {
+        let mut score = 0;
+
+        if let (Some(preferred), Some(language)) = (&self.language, &root_file.language) {
+            if !language_matches(preferred, language) {
+                return None;
+            }
+            score += 1;
+        }
+        if let Some(preferred) = &self.access_mode {
+            if !root_file.access_modes.is_empty() {
+                if !root_file.access_modes.contains(preferred) {
+                    return None;
+                }
+                score += 1;
+            }
+        }
+        for (preferred, actual) in [
+            (&self.layout, &root_file.layout),
+            (&self.media, &root_file.media),
+        ] {
+            if let (Some(preferred), Some(actual)) = (preferred, actual) {
+                if preferred != actual {
+                    return None;
+                }
+                score += 1;
+            }
+        }
+
+        Some(score)
     }
 }
 
+/// whether `preferred` is `tag` or a prefix of it, e.g. `ja` for `ja-JP`
+fn language_matches(preferred: &str, tag: &str) -> bool {
+    let preferred = preferred.to_ascii_lowercase();
+    let tag = tag.to_ascii_lowercase();
+
+    tag == preferred || tag.starts_with(&format!("{}-", preferred))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -61,16 +173,69 @@ mod tests {
         let expected = Container {
             root_files: vec![
                 RootFile {
-                    base_path: "epub".to_string(),
                     full_path: "epub/content.opf".to_string(),
+                    media_type: Some("application/oebps-package+xml".to_string()),
+                    ..Default::default()
                 },
                 RootFile {
-                    base_path: "EPUB".to_string(),
                     full_path: "EPUB/content.opf".to_string(),
+                    media_type: Some("application/oebps-package+xml".to_string()),
+                    ..Default::default()
                 },
             ],
         };
 
         assert_eq!(parsed, expected)
     }
+
+    #[test]
+    fn select_rendition() {
+        let doc = r###"
+<?xml version="1.0" encoding="utf-8"?>
+<container xmlns="urn:oasis:names:tc:opendocument:xmlns:container"
+           xmlns:rendition="http://www.idpf.org/2013/rendition" version="1.0">
+    <rootfiles>
+        <rootfile full-path="reflowable/content.opf" media-type="application/oebps-package+xml"/>
+        <rootfile full-path="fixed/content.opf" media-type="application/oebps-package+xml"
+                  rendition:layout="pre-paginated" rendition:accessMode="visual"/>
+        <rootfile full-path="ja/content.opf" media-type="application/oebps-package+xml"
+                  rendition:language="ja-JP" rendition:label="日本語"/>
+        <rootfile full-path="book.pdf" media-type="application/pdf"/>
+    </rootfiles>
+</container>
+        "###
+        .trim();
+
+        let container = Container::from(doc).unwrap();
+        assert_eq!(3, container.renditions().count());
+
+        let select =
+            |selector: RenditionSelector| container.select(&selector).unwrap().full_path.as_str();
+        assert_eq!(
+            "reflowable/content.opf",
+            select(RenditionSelector::default())
+        );
+        assert_eq!(
+            "fixed/content.opf",
+            select(RenditionSelector {
+                layout: Some("pre-paginated".to_string()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            "ja/content.opf",
+            select(RenditionSelector {
+                language: Some("ja".to_string()),
+                layout: Some("reflowable".to_string()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            "reflowable/content.opf",
+            select(RenditionSelector {
+                access_mode: Some("auditory".to_string()),
+                ..Default::default()
+            })
+        );
+    }
 }
@@ -0,0 +1,63 @@
+use roxmltree::Node;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::util::path;
+
+const NAMESPACE_XLINK: &str = "http://www.w3.org/1999/xlink";
+
+/// cover image of a book
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Cover {
+    /// resource path of the image
+    pub path: String,
+    /// mime type declared in the manifest, or guessed from the file extension
+    pub media_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// Find the first image referenced by an XHTML page, either through `<img src>` or an SVG
+/// `<image xlink:href>`, resolved against the page path.
+pub(crate) fn first_image(doc: &str, page_path: &str) -> Option<String> {
+    let doc = roxmltree::Document::parse(doc).ok()?;
+    let href = doc.descendants().find_map(|node| image_href(&node))?;
+
+    Some(path::resolve(page_path, href))
+}
+
+fn image_href<'a>(node: &Node<'a, '_>) -> Option<&'a str> {
+    match node.tag_name().name() {
+        "img" => node.attribute("src"),
+        "image" => node
+            .attribute((NAMESPACE_XLINK, "href"))
+            .or_else(|| node.attribute("href")),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_first_image() {
+        let doc = r#"
+<?xml version="1.0" encoding="utf-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head><title>Cover</title></head>
+<body>
+    <svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" viewBox="0 0 600 800">
+        <image width="600" height="800" xlink:href="images/cover.jpg"/>
+    </svg>
+    <img src="images/logo.png" alt=""/>
+</body>
+</html>"#
+            .trim();
+
+        assert_eq!(
+            Some(String::from("epub/text/images/cover.jpg")),
+            first_image(doc, "epub/text/cover.xhtml")
+        );
+    }
+}
@@ -0,0 +1,243 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use crate::error::{Error, Result};
+use crate::parser::protection::Drm;
+use crate::parser::Metadata;
+use crate::util::{path, xml_util};
+
+pub(crate) const ENCRYPTION_PATH: &str = "META-INF/encryption.xml";
+
+const NAMESPACE_XMLENC: &str = "http://www.w3.org/2001/04/xmlenc#";
+const NAMESPACE_XMLDSIG: &str = "http://www.w3.org/2000/09/xmldsig#";
+pub(crate) const NAMESPACE_ADEPT: &str = "http://ns.adobe.com/adept";
+
+/// `RetrievalMethod@Type` of content keys held by a Readium LCP license
+const LCP_CONTENT_KEY: &str = "http://readium.org/2014/01/lcp#EncryptedContentKey";
+
+/// font obfuscation of the EPUB specification
+pub(crate) const IDPF_OBFUSCATION: &str = "http://www.idpf.org/2008/embedding";
+/// Adobe's font obfuscation, predating the IDPF one
+pub(crate) const ADOBE_OBFUSCATION: &str = "http://ns.adobe.com/pdf/enc#RC";
+
+/// resources listed in `META-INF/encryption.xml`
+#[derive(Debug, PartialEq, Clone, Default)]
+pub(crate) struct Encryption {
+    pub resources: Vec<EncryptedResource>,
+    /// DRM schemes the content keys are retrieved from
+    pub drm: Vec<Drm>,
+}
+
+/// a resource listed in `META-INF/encryption.xml`
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EncryptedResource {
+    /// resource path
+    pub path: String,
+    /// `EncryptionMethod@Algorithm`
+    pub algorithm: String,
+}
+
+/// a font obfuscation algorithm
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum Obfuscation {
+    Idpf,
+    Adobe,
+}
+
+impl Encryption {
+    pub(crate) fn from(doc: &str) -> Result<Encryption> {
+        let doc = xml_util::parse(doc, ENCRYPTION_PATH)?;
+
+        let resources = doc
+            .descendants()
+            .filter(|node| node.has_tag_name((NAMESPACE_XMLENC, "EncryptedData")))
+            .filter_map(|node| {
+                let algorithm = node
+                    .children()
+                    .find(|node| node.has_tag_name((NAMESPACE_XMLENC, "EncryptionMethod")))?
+                    .attribute("Algorithm")?;
+                let uri = node
+                    .descendants()
+                    .find(|node| node.has_tag_name((NAMESPACE_XMLENC, "CipherReference")))?
+                    .attribute("URI")?;
+                Some(EncryptedResource {
+                    // URIs are relative to the root of the container
+                    path: path::resolve("", uri),
+                    algorithm: algorithm.trim().to_string(),
+                })
+            })
+            .collect();
+
+        let mut drm = Vec::new();
+        let lcp = doc.descendants().any(|node| {
+            node.has_tag_name((NAMESPACE_XMLDSIG, "RetrievalMethod"))
+                && (node.attribute("Type") == Some(LCP_CONTENT_KEY)
+                    || node
+                        .attribute("URI")
+                        .is_some_and(|uri| uri.starts_with("license.lcpl")))
+        });
+        if lcp {
+            drm.push(Drm::ReadiumLcp);
+        }
+        if doc
+            .descendants()
+            .any(|node| node.tag_name().namespace() == Some(NAMESPACE_ADEPT))
+        {
+            drm.push(Drm::AdobeAdept);
+        }
+
+        Ok(Encryption { resources, drm })
+    }
+
+    /// Fail with `Error::EncryptedResource` when the resource at `path` is encrypted.
+    /// Obfuscated fonts pass.
+    pub(crate) fn check(&self, path: &str) -> Result<()> {
+        match self.resources.iter().find(|resource| resource.path == path) {
+            Some(resource) if !resource.is_obfuscated() => Err(Error::EncryptedResource {
+                path: resource.path.clone(),
+                algorithm: resource.algorithm.clone(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Font obfuscation applied to the resource at `path`, if any.
+    pub(crate) fn obfuscation(&self, path: &str) -> Option<Obfuscation> {
+        self.resources
+            .iter()
+            .find(|resource| resource.path == path)
+            .and_then(|resource| Obfuscation::from_algorithm(&resource.algorithm))
+    }
+}
+
+impl EncryptedResource {
+    /// whether the resource is an obfuscated font rather than encrypted
+    pub fn is_obfuscated(&self) -> bool {
+        Obfuscation::from_algorithm(&self.algorithm).is_some()
+    }
+}
+
+impl Obfuscation {
+    pub(crate) fn from_algorithm(algorithm: &str) -> Option<Obfuscation> {
+        match algorithm {
+            IDPF_OBFUSCATION => Some(Obfuscation::Idpf),
+            ADOBE_OBFUSCATION => Some(Obfuscation::Adobe),
+            _ => None,
+        }
+    }
+
+    /// Undo the obfuscation of `data` in place. Obfuscating is the same operation. Data is
+    /// left unchanged when `metadata` has no identifier to derive the key from.
+    pub(crate) fn deobfuscate(&self, data: &mut [u8], metadata: &Metadata) {
+        let Some(key) = self.key(metadata) else {
+            return;
+        };
+        let len = match self {
+            Obfuscation::Idpf => 1040,
+            Obfuscation::Adobe => 1024,
+        };
+
+        for (byte, key) in data.iter_mut().take(len).zip(key.iter().cycle()) {
+            *byte ^= key;
+        }
+    }
+
+    fn key(&self, metadata: &Metadata) -> Option<Vec<u8>> {
+        match self {
+            // SHA-1 of the unique identifier without whitespace
+            Obfuscation::Idpf => {
+                let identifier: String = metadata
+                    .unique_identifier
+                    .as_deref()?
+                    .chars()
+                    .filter(|c| !matches!(c, ' ' | '\t' | '\r' | '\n'))
+                    .collect();
+                Some(Sha1::digest(identifier.as_bytes()).to_vec())
+            }
+            // the 16 bytes of the `urn:uuid:` identifier, the unique one first
+            Obfuscation::Adobe => metadata
+                .unique_identifier
+                .iter()
+                .chain(
+                    metadata
+                        .identifiers
+                        .iter()
+                        .map(|identifier| &identifier.value),
+                )
+                .find_map(|identifier| uuid_bytes(identifier)),
+        }
+    }
+}
+
+fn uuid_bytes(identifier: &str) -> Option<Vec<u8>> {
+    let identifier = identifier.trim();
+    let uuid = identifier
+        .get(.."urn:uuid:".len())
+        .filter(|prefix| prefix.eq_ignore_ascii_case("urn:uuid:"))
+        .map_or(identifier, |prefix| &identifier[prefix.len()..]);
+    let hex: Vec<u8> = uuid.bytes().filter(|byte| *byte != b'-').collect();
+    if hex.len() != 32 {
+        return None;
+    }
+
+    hex.chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_encryption() {
+        let doc = r#"<?xml version="1.0" encoding="utf-8"?>
+<encryption xmlns="urn:oasis:names:tc:opendocument:xmlns:container"
+            xmlns:enc="http://www.w3.org/2001/04/xmlenc#">
+    <enc:EncryptedData>
+        <enc:EncryptionMethod Algorithm="http://www.idpf.org/2008/embedding"/>
+        <enc:CipherData><enc:CipherReference URI="epub/fonts/Serif%20Bold.otf"/></enc:CipherData>
+    </enc:EncryptedData>
+    <enc:EncryptedData>
+        <enc:EncryptionMethod Algorithm="http://ns.adobe.com/pdf/enc#RC"/>
+        <enc:CipherData><enc:CipherReference URI="epub/fonts/sans.ttf"/></enc:CipherData>
+    </enc:EncryptedData>
+</encryption>"#;
+
+        let encryption = Encryption::from(doc).unwrap();
+        assert_eq!(
+            Some(Obfuscation::Idpf),
+            encryption.obfuscation("epub/fonts/Serif Bold.otf")
+        );
+        assert_eq!(
+            Some(Obfuscation::Adobe),
+            encryption.obfuscation("epub/fonts/sans.ttf")
+        );
+        assert_eq!(None, encryption.obfuscation("epub/chapter-1.xhtml"));
+
+        let metadata = Metadata {
+            unique_identifier: Some(String::from(
+                " urn:uuid:d3a1e1f2-8a55-4b5b-9f0e-3c7e9d3c2b10\n",
+            )),
+            ..Default::default()
+        };
+        assert_eq!(
+            "d7fec1f4b7f6652b4b5a81f8e2cc42e84e091b70",
+            Obfuscation::Idpf
+                .key(&metadata)
+                .unwrap()
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>()
+        );
+        assert_eq!(
+            vec![
+                0xd3, 0xa1, 0xe1, 0xf2, 0x8a, 0x55, 0x4b, 0x5b, 0x9f, 0x0e, 0x3c, 0x7e, 0x9d, 0x3c,
+                0x2b, 0x10
+            ],
+            Obfuscation::Adobe.key(&metadata).unwrap()
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use roxmltree::Node;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::util::path;
+
+/// manifest items in document order
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Manifest {
+    pub items: Vec<ManifestItem>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ManifestItem {
+    pub id: String,
+    /// `href` as written in the package document
+    pub href: String,
+    /// resource path in the archive
+    pub path: String,
+    /// mime type
+    pub media_type: Option<String>,
+    /// e.g. `nav`, `cover-image`, `scripted`, `svg`, `mathml` or `remote-resources`
+    pub properties: Vec<String>,
+    /// id of the fallback item
+    pub fallback: Option<String>,
+    /// id of the media overlay (SMIL) item
+    pub media_overlay: Option<String>,
+}
+
+impl Manifest {
+    pub(crate) fn from(manifest_elem: &Node, doc_path: &str) -> Manifest {
+        let items = manifest_elem
+            .children()
+            .filter(|node| node.has_tag_name("item"))
+            .filter_map(|node| {
+                let href = node.attribute("href")?;
+                Some(ManifestItem {
+                    id: node.attribute("id")?.to_string(),
+                    href: href.to_string(),
+                    path: path::resolve(doc_path, href),
+                    media_type: node.attribute("media-type").map(|str| str.to_string()),
+                    properties: node
+                        .attribute("properties")
+                        .map(|properties| {
+                            properties
+                                .split_ascii_whitespace()
+                                .map(|str| str.to_string())
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                    fallback: node.attribute("fallback").map(|str| str.to_string()),
+                    media_overlay: node.attribute("media-overlay").map(|str| str.to_string()),
+                })
+            })
+            .collect();
+
+        Manifest { items }
+    }
+
+    pub fn by_id(&self, id: &str) -> Option<&ManifestItem> {
+        self.items.iter().find(|item| item.id == id)
+    }
+
+    pub fn by_path(&self, path: &str) -> Option<&ManifestItem> {
+        self.items.iter().find(|item| item.path == path)
+    }
+
+    /// the first item with the given property, e.g. `nav`
+    pub fn by_property(&self, property: &str) -> Option<&ManifestItem> {
+        self.items.iter().find(|item| item.has_property(property))
+    }
+
+    /// The item with the given id followed by its fallbacks, in the order a reading system
+    /// should try them. Stops at a missing or already visited id.
+    pub fn fallback_chain(&self, id: &str) -> Vec<&ManifestItem> {
+        let mut chain: Vec<&ManifestItem> = Vec::new();
+        let mut next = self.by_id(id);
+        while let Some(item) = next {
+            if chain.iter().any(|visited| visited.id == item.id) {
+                break;
+            }
+            chain.push(item);
+            next = item.fallback.as_deref().and_then(|id| self.by_id(id));
+        }
+
+        chain
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, ManifestItem> {
+        self.items.iter()
+    }
+}
+
+impl ManifestItem {
+    pub fn has_property(&self, property: &str) -> bool {
+        self.properties.iter().any(|p| p == property)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follow_fallback_chain() {
+        let doc = r#"
+<manifest xmlns="http://www.idpf.org/2007/opf">
+    <item id="page" href="page.xhtml" media-type="application/xhtml+xml" properties="scripted svg" media-overlay="page-smil"/>
+    <item id="page-smil" href="page.smil" media-type="application/smil+xml"/>
+    <item id="comic" href="comic.cbz" media-type="application/x-cbz" fallback="comic-svg"/>
+    <item id="comic-svg" href="comic.svg" media-type="image/svg+xml" fallback="comic"/>
+</manifest>"#
+            .trim();
+
+        let doc = roxmltree::Document::parse(doc).unwrap();
+        let manifest = Manifest::from(&doc.root_element(), "epub/content.opf");
+
+        let page = manifest.by_path("epub/page.xhtml").unwrap();
+        assert_eq!(vec!["scripted", "svg"], page.properties);
+        assert!(page.has_property("svg"));
+        assert_eq!(Some("page-smil"), page.media_overlay.as_deref());
+
+        let chain: Vec<&str> = manifest
+            .fallback_chain("comic")
+            .iter()
+            .map(|item| item.path.as_str())
+            .collect();
+        assert_eq!(vec!["epub/comic.cbz", "epub/comic.svg"], chain);
+    }
+}
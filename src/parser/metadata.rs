@@ -0,0 +1,517 @@
+use roxmltree::{ExpandedName, Node};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+pub(crate) const NAMESPACE_DC: &str = "http://purl.org/dc/elements/1.1/";
+pub(crate) const NAMESPACE_OPF: &str = "http://www.idpf.org/2007/opf";
+pub(crate) const NAMESPACE_XML: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// `scheme` of `identifier-type` refinements holding an ONIX code list 5 value
+pub(crate) const ONIX_CODELIST5: &str = "onix:codelist5";
+/// ONIX code list 5 values and the identifier scheme they stand for
+const ONIX_IDENTIFIER_TYPES: [(&str, &str); 12] = [
+    ("02", "ISBN"),
+    ("15", "ISBN"),
+    ("03", "GTIN"),
+    ("14", "GTIN"),
+    ("04", "UPC"),
+    ("05", "ISMN"),
+    ("25", "ISMN"),
+    ("06", "DOI"),
+    ("13", "LCCN"),
+    ("22", "URN"),
+    ("23", "OCLC"),
+    ("35", "ARK"),
+];
+
+/// Dublin Core metadata of a package document, in document order
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Metadata {
+    /// value of the identifier referenced by `package@unique-identifier`
+    pub unique_identifier: Option<String>,
+    pub identifiers: Vec<Identifier>,
+    pub titles: Vec<Title>,
+    pub languages: Vec<String>,
+    pub creators: Vec<Creator>,
+    pub contributors: Vec<Creator>,
+    pub publishers: Vec<String>,
+    pub dates: Vec<Date>,
+    pub descriptions: Vec<String>,
+    pub subjects: Vec<String>,
+    pub rights: Vec<String>,
+    pub sources: Vec<String>,
+    pub types: Vec<String>,
+    pub formats: Vec<String>,
+    pub relations: Vec<String>,
+    pub coverages: Vec<String>,
+    /// `dcterms:modified`
+    pub modified: Option<String>,
+    /// every `meta` element, EPUB 2 `name`/`content` pairs included
+    pub metas: Vec<Meta>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Identifier {
+    pub id: Option<String>,
+    pub value: String,
+    /// e.g. `ISBN`, `UUID` or `DOI`, declared by `opf:scheme` or an `identifier-type` refinement,
+    /// or inferred from a URN prefix. ONIX code list 5 refinements are mapped to the scheme
+    /// name, other refinement values are kept as is.
+    pub scheme: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Title {
+    pub id: Option<String>,
+    pub value: String,
+    /// `xml:lang`
+    pub lang: Option<String>,
+    /// `title-type` refinement, e.g. `main`, `subtitle` or `collection`
+    pub title_type: Option<String>,
+    pub file_as: Option<String>,
+    pub display_seq: Option<u32>,
+    pub alternate_scripts: Vec<AlternateScript>,
+}
+
+/// `dc:creator` or `dc:contributor`
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Creator {
+    pub id: Option<String>,
+    pub name: String,
+    /// MARC relator code, e.g. `aut` or `ill`
+    pub role: Option<String>,
+    /// sortable form of the name, e.g. `Brontë, Charlotte`
+    pub file_as: Option<String>,
+    /// `xml:lang`
+    pub lang: Option<String>,
+    pub display_seq: Option<u32>,
+    pub alternate_scripts: Vec<AlternateScript>,
+}
+
+/// the same value rendered in another language or script, e.g. a Japanese author name in Latin
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AlternateScript {
+    pub value: String,
+    /// `xml:lang`
+    pub lang: Option<String>,
+}
+
+/// EPUB 3 `<meta property="...">` or EPUB 2 `<meta name="..." content="..."/>`
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Meta {
+    pub id: Option<String>,
+    /// `property`, or `name` for EPUB 2
+    pub property: String,
+    /// text content, or `content` for EPUB 2
+    pub value: String,
+    /// id of the refined element, without the leading `#`
+    pub refines: Option<String>,
+    pub scheme: Option<String>,
+    /// `xml:lang`
+    pub lang: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Date {
+    pub id: Option<String>,
+    pub value: String,
+    /// EPUB 2 `opf:event`, e.g. `publication` or `modification`
+    pub event: Option<String>,
+}
+
+impl Metadata {
+    pub(crate) fn from(metadata_elem: &Node, unique_identifier_id: Option<&str>) -> Metadata {
+        let mut metadata = Metadata::default();
+
+        for node in metadata_elem.children().filter(|node| node.is_element()) {
+            if node.has_tag_name((NAMESPACE_OPF, "meta")) {
+                if let Some(meta) = parse_meta(&node) {
+                    metadata.metas.push(meta);
+                }
+                continue;
+            }
+            if node.tag_name().namespace() != Some(NAMESPACE_DC) {
+                continue;
+            }
+
+            let id = node.attribute("id").map(|str| str.to_string());
+            let value = text(&node);
+
+            match node.tag_name().name() {
+                "identifier" => {
+                    if unique_identifier_id.is_some()
+                        && node.attribute("id") == unique_identifier_id
+                    {
+                        metadata.unique_identifier = Some(value.clone());
+                    }
+                    let scheme = node
+                        .attribute((NAMESPACE_OPF, "scheme"))
+                        .map(|str| str.to_string())
+                        .or_else(|| infer_identifier_scheme(&value));
+                    metadata.identifiers.push(Identifier { id, value, scheme });
+                }
+                "title" => metadata.titles.push(Title {
+                    id,
+                    value,
+                    lang: attribute(&node, (NAMESPACE_XML, "lang")),
+                    ..Default::default()
+                }),
+                "language" => metadata.languages.push(value),
+                "creator" => metadata.creators.push(parse_creator(&node, id, value)),
+                "contributor" => metadata.contributors.push(parse_creator(&node, id, value)),
+                "publisher" => metadata.publishers.push(value),
+                "date" => metadata.dates.push(Date {
+                    id,
+                    value,
+                    event: attribute(&node, (NAMESPACE_OPF, "event")),
+                }),
+                "description" => metadata.descriptions.push(value),
+                "subject" => metadata.subjects.push(value),
+                "rights" => metadata.rights.push(value),
+                "source" => metadata.sources.push(value),
+                "type" => metadata.types.push(value),
+                "format" => metadata.formats.push(value),
+                "relation" => metadata.relations.push(value),
+                "coverage" => metadata.coverages.push(value),
+                _ => {}
+            }
+        }
+
+        metadata.refine();
+        metadata.modified = metadata
+            .metas
+            .iter()
+            .find(|meta| meta.refines.is_none() && meta.property == "dcterms:modified")
+            .map(|meta| meta.value.clone());
+
+        metadata
+    }
+
+    /// Resolve `<meta refines="#id">` onto the refined titles, creators and identifiers. A
+    /// meta refining another meta applies to the element at the end of the chain.
+    fn refine(&mut self) {
+        let refinements: Vec<(String, Meta)> = self
+            .metas
+            .iter()
+            .filter_map(|meta| {
+                let target = self.refined_id(meta.refines.as_deref()?);
+                Some((target.to_string(), meta.clone()))
+            })
+            .collect();
+        for (target, meta) in &refinements {
+            let value = meta.value.clone();
+
+            if let Some(title) = self
+                .titles
+                .iter_mut()
+                .find(|title| title.id.as_ref() == Some(target))
+            {
+                match meta.property.as_str() {
+                    "title-type" => title.title_type = Some(value),
+                    "file-as" => title.file_as = Some(value),
+                    "display-seq" => title.display_seq = value.parse().ok(),
+                    "alternate-script" => title.alternate_scripts.push(AlternateScript {
+                        value,
+                        lang: meta.lang.clone(),
+                    }),
+                    _ => {}
+                }
+            } else if let Some(creator) = self
+                .creators
+                .iter_mut()
+                .chain(self.contributors.iter_mut())
+                .find(|creator| creator.id.as_ref() == Some(target))
+            {
+                match meta.property.as_str() {
+                    "role" => creator.role = Some(value),
+                    "file-as" => creator.file_as = Some(value),
+                    "display-seq" => creator.display_seq = value.parse().ok(),
+                    "alternate-script" => creator.alternate_scripts.push(AlternateScript {
+                        value,
+                        lang: meta.lang.clone(),
+                    }),
+                    _ => {}
+                }
+            } else if let Some(identifier) = self
+                .identifiers
+                .iter_mut()
+                .find(|identifier| identifier.id.as_ref() == Some(target))
+            {
+                if meta.property == "identifier-type" {
+                    identifier.scheme = Some(identifier_scheme(meta.scheme.as_deref(), value));
+                }
+            }
+        }
+    }
+
+    /// Id of the element a refinement of `target` refines, following refinements of
+    /// refinements. Cycles end after every meta was visited once.
+    pub(crate) fn refined_id<'m>(&'m self, mut target: &'m str) -> &'m str {
+        for _ in 0..self.metas.len() {
+            match self
+                .metas
+                .iter()
+                .find(|meta| meta.id.as_deref() == Some(target))
+            {
+                Some(Meta {
+                    refines: Some(refines),
+                    ..
+                }) => target = refines,
+                _ => break,
+            }
+        }
+
+        target
+    }
+
+    /// the title refined as `main`, otherwise the first `dc:title`
+    pub fn title(&self) -> Option<&str> {
+        self.titles
+            .iter()
+            .find(|title| title.title_type.as_deref() == Some("main"))
+            .or_else(|| self.titles.first())
+            .map(|title| title.value.as_str())
+    }
+
+    /// the first `dc:language`
+    pub fn language(&self) -> Option<&str> {
+        self.languages.first().map(|language| language.as_str())
+    }
+}
+
+fn parse_creator(node: &Node, id: Option<String>, name: String) -> Creator {
+    Creator {
+        id,
+        name,
+        role: attribute(node, (NAMESPACE_OPF, "role")),
+        file_as: attribute(node, (NAMESPACE_OPF, "file-as")),
+        lang: attribute(node, (NAMESPACE_XML, "lang")),
+        ..Default::default()
+    }
+}
+
+fn parse_meta(node: &Node) -> Option<Meta> {
+    let (property, value) = if let Some(property) = node.attribute("property") {
+        (property, text(node))
+    } else {
+        (node.attribute("name")?, attribute(node, "content")?)
+    };
+
+    Some(Meta {
+        id: attribute(node, "id"),
+        property: property.trim().to_string(),
+        value,
+        refines: node
+            .attribute("refines")
+            .map(|str| str.trim().trim_start_matches('#').to_string()),
+        scheme: attribute(node, "scheme"),
+        lang: attribute(node, (NAMESPACE_XML, "lang")),
+    })
+}
+
+fn attribute<'n, 'm, N>(node: &Node, name: N) -> Option<String>
+where
+    N: Into<ExpandedName<'n, 'm>>,
+{
+    node.attribute(name).map(|str| str.trim().to_string())
+}
+
+fn text(node: &Node) -> String {
+    node.text().unwrap_or_default().trim().to_string()
+}
+
+/// The scheme an `identifier-type` refinement stands for. ONIX codes are mapped to their
+/// scheme name, other values are kept as is.
+fn identifier_scheme(scheme: Option<&str>, value: String) -> String {
+    match scheme {
+        Some(ONIX_CODELIST5) => ONIX_IDENTIFIER_TYPES
+            .iter()
+            .find(|(code, _)| *code == value)
+            .map_or(value, |(_, name)| name.to_string()),
+        _ => value,
+    }
+}
+
+fn infer_identifier_scheme(value: &str) -> Option<String> {
+    let value = value.to_ascii_lowercase();
+    let scheme = if value.starts_with("urn:isbn:") {
+        "ISBN"
+    } else if value.starts_with("urn:uuid:") {
+        "UUID"
+    } else if value.starts_with("urn:doi:") || value.starts_with("doi:") {
+        "DOI"
+    } else {
+        return None;
+    };
+
+    Some(scheme.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_metadata() {
+        let doc = r###"
+<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" unique-identifier="BookId" version="2.0">
+    <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+        <dc:title xml:lang="en">Jane Eyre</dc:title>
+        <dc:creator opf:role="aut" opf:file-as="Brontë, Charlotte">Charlotte Brontë</dc:creator>
+        <dc:contributor opf:role="ill">F. H. Townsend</dc:contributor>
+        <dc:identifier id="BookId" opf:scheme="UUID">urn:uuid:0f3a4c52-7d2b-4e44-9a3c-2a0f5c6d9b11</dc:identifier>
+        <dc:identifier>urn:isbn:9780141441146</dc:identifier>
+        <dc:language>en</dc:language>
+        <dc:language>fr</dc:language>
+        <dc:publisher>Penguin</dc:publisher>
+        <dc:date opf:event="publication">1847-10-16</dc:date>
+        <dc:description> An orphan becomes a governess. </dc:description>
+        <dc:subject>Fiction</dc:subject>
+        <dc:subject>Governesses</dc:subject>
+        <dc:rights>Public domain</dc:rights>
+        <dc:source>Smith, Elder &amp; Co.</dc:source>
+        <meta name="cover" content="cover-image"/>
+    </metadata>
+</package>
+        "###
+        .trim();
+
+        let doc = roxmltree::Document::parse(doc).unwrap();
+        let metadata_elem = doc
+            .root_element()
+            .children()
+            .find(|node| node.has_tag_name("metadata"))
+            .unwrap();
+
+        let expected = Metadata {
+            unique_identifier: Some(String::from(
+                "urn:uuid:0f3a4c52-7d2b-4e44-9a3c-2a0f5c6d9b11",
+            )),
+            identifiers: vec![
+                Identifier {
+                    id: Some(String::from("BookId")),
+                    value: String::from("urn:uuid:0f3a4c52-7d2b-4e44-9a3c-2a0f5c6d9b11"),
+                    scheme: Some(String::from("UUID")),
+                },
+                Identifier {
+                    id: None,
+                    value: String::from("urn:isbn:9780141441146"),
+                    scheme: Some(String::from("ISBN")),
+                },
+            ],
+            titles: vec![Title {
+                id: None,
+                value: String::from("Jane Eyre"),
+                lang: Some(String::from("en")),
+                ..Default::default()
+            }],
+            languages: vec![String::from("en"), String::from("fr")],
+            creators: vec![Creator {
+                id: None,
+                name: String::from("Charlotte Brontë"),
+                role: Some(String::from("aut")),
+                file_as: Some(String::from("Brontë, Charlotte")),
+                ..Default::default()
+            }],
+            contributors: vec![Creator {
+                id: None,
+                name: String::from("F. H. Townsend"),
+                role: Some(String::from("ill")),
+                ..Default::default()
+            }],
+            publishers: vec![String::from("Penguin")],
+            dates: vec![Date {
+                id: None,
+                value: String::from("1847-10-16"),
+                event: Some(String::from("publication")),
+            }],
+            descriptions: vec![String::from("An orphan becomes a governess.")],
+            subjects: vec![String::from("Fiction"), String::from("Governesses")],
+            rights: vec![String::from("Public domain")],
+            sources: vec![String::from("Smith, Elder & Co.")],
+            metas: vec![Meta {
+                property: String::from("cover"),
+                value: String::from("cover-image"),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let parsed = Metadata::from(&metadata_elem, Some("BookId"));
+
+        assert_eq!(expected, parsed)
+    }
+
+    #[test]
+    fn resolve_refinements() {
+        let doc = r###"
+<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" unique-identifier="uid" version="3.0">
+    <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+        <dc:identifier id="uid">9780141441146</dc:identifier>
+        <meta refines="#uid" property="identifier-type" scheme="onix:codelist5">15</meta>
+        <dc:title id="subtitle">An Autobiography</dc:title>
+        <meta refines="#subtitle" property="title-type">subtitle</meta>
+        <dc:title id="title">Jane Eyre</dc:title>
+        <meta refines="#title" property="title-type">main</meta>
+        <meta refines="#title" property="display-seq">1</meta>
+        <dc:creator id="author">Charlotte Brontë</dc:creator>
+        <meta refines="#author" property="role" scheme="marc:relators">aut</meta>
+        <meta refines="#author" property="file-as">Brontë, Charlotte</meta>
+        <meta refines="#author" property="alternate-script" xml:lang="ja">シャーロット・ブロンテ</meta>
+        <dc:contributor id="illustrator">Fritz Eichenberg</dc:contributor>
+        <meta refines="#illustrator" property="role" scheme="marc:relators" id="illustrator-role">ill</meta>
+        <meta refines="#illustrator-role" property="file-as" id="illustrator-file-as">Eichenberg, Fritz</meta>
+        <meta refines="#illustrator-file-as" property="display-seq">2</meta>
+        <meta refines="#cycle-b" property="file-as" id="cycle-a">A</meta>
+        <meta refines="#cycle-a" property="file-as" id="cycle-b">B</meta>
+        <meta property="dcterms:modified">2023-01-01T00:00:00Z</meta>
+    </metadata>
+</package>
+        "###
+        .trim();
+
+        let doc = roxmltree::Document::parse(doc).unwrap();
+        let metadata_elem = doc
+            .root_element()
+            .children()
+            .find(|node| node.has_tag_name("metadata"))
+            .unwrap();
+
+        let parsed = Metadata::from(&metadata_elem, Some("uid"));
+
+        assert_eq!(Some("Jane Eyre"), parsed.title());
+        assert_eq!(Some(String::from("subtitle")), parsed.titles[0].title_type);
+        assert_eq!(Some(1), parsed.titles[1].display_seq);
+        assert_eq!(Some(String::from("ISBN")), parsed.identifiers[0].scheme);
+        assert_eq!(
+            Creator {
+                id: Some(String::from("author")),
+                name: String::from("Charlotte Brontë"),
+                role: Some(String::from("aut")),
+                file_as: Some(String::from("Brontë, Charlotte")),
+                lang: None,
+                display_seq: None,
+                alternate_scripts: vec![AlternateScript {
+                    value: String::from("シャーロット・ブロンテ"),
+                    lang: Some(String::from("ja")),
+                }],
+            },
+            parsed.creators[0]
+        );
+        // refinements of refinements reach the contributor through the chain
+        let illustrator = &parsed.contributors[0];
+        assert_eq!(Some("ill"), illustrator.role.as_deref());
+        assert_eq!(Some("Eichenberg, Fritz"), illustrator.file_as.as_deref());
+        assert_eq!(Some(2), illustrator.display_seq);
+        assert_eq!(Some(String::from("2023-01-01T00:00:00Z")), parsed.modified);
+    }
+}
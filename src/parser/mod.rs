@@ -1,25 +1,73 @@
-use std::collections::HashMap;
 use std::fs::File;
+use std::io::{Cursor, Read, Seek};
+use std::sync::Arc;
 
-use anyhow::{anyhow, Result};
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 use zip::ZipArchive;
 
-use crate::parser::container::Container;
-use crate::parser::package_document::PackageDocument;
-pub use crate::parser::toc::{Toc, TocNode};
+use crate::editor::{Editor, Package};
+use crate::error::{Error, Result};
+pub use crate::parser::book::{Book, SharedFile};
+pub(crate) use crate::parser::container::Container;
+pub use crate::parser::container::{RenditionSelector, RootFile};
+pub use crate::parser::cover::Cover;
+pub use crate::parser::encryption::EncryptedResource;
+use crate::parser::encryption::{Encryption, ENCRYPTION_PATH};
+pub use crate::parser::manifest::{Manifest, ManifestItem};
+pub(crate) use crate::parser::metadata::ONIX_CODELIST5;
+pub use crate::parser::metadata::{
+    AlternateScript, Creator, Date, Identifier, Meta, Metadata, Title,
+};
+pub(crate) use crate::parser::package_document::PackageDocument;
+use crate::parser::protection::RIGHTS_PATH;
+pub use crate::parser::protection::{Drm, Protection};
+pub use crate::parser::rendition::{
+    Flow, Layout, Orientation, PageSpread, Rendition, Spread, Viewport,
+};
+pub use crate::parser::search::{SearchMatch, SearchOptions};
+pub use crate::parser::spine::{PageProgressionDirection, Spine, SpineItem};
+pub use crate::parser::text::{PageText, SpineText, TextOptions};
+pub(crate) use crate::parser::toc::TocSource;
+pub use crate::parser::toc::{Href, Landmark, NavList, PageTarget, Toc, TocNode};
+use crate::util::media_type;
 use crate::util::zip_util::{read_binary_file, read_text_file};
+use crate::validator::{self, Report};
 
+mod book;
 mod container;
+mod cover;
+mod encryption;
+mod manifest;
+mod metadata;
 mod package_document;
+mod protection;
+mod rendition;
+mod search;
+mod spine;
+mod text;
 mod toc;
 
-const CONTAINER_PATH: &str = "META-INF/container.xml";
+pub(crate) const CONTAINER_PATH: &str = "META-INF/container.xml";
+
+/// result kept for later lookups, a failure is returned to each of them as `Error::Cached`
+type Cached<T> = std::result::Result<T, Arc<Error>>;
 
 #[derive(Debug)]
-pub struct Parser {
-    archive: ZipArchive<File>,
+pub struct Parser<R: Read + Seek = File> {
+    archive: ZipArchive<R>,
+    /// `META-INF/container.xml`, read when the book is opened
+    container: Cached<Container>,
+    /// package document path of the rendition in use, the default rendition if `None`
+    rendition: Option<String>,
+    /// package document of the rendition in use, `None` until first needed
+    package: Option<Cached<PackageDocument>>,
+    /// `meta` of the rendition in use, `None` until first needed
+    meta: Option<Cached<BookMeta>>,
+    /// `toc` of the rendition in use, `None` until first needed
+    toc: Option<Toc>,
+    /// `META-INF/encryption.xml`, `None` until first needed
+    encryption: Option<Cached<Encryption>>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -27,53 +75,1039 @@ pub struct Parser {
 pub struct BookMeta {
     /// book title
     pub title: String,
-    /// `HashMap<resource path, Option<mime type>>`
-    pub manifest: HashMap<String, Option<String>>,
-    /// list of all page paths
-    pub spine: Vec<String>,
-    /// table of contents
+    /// Dublin Core metadata
+    pub metadata: Metadata,
+    /// all resources of the book, in document order
+    pub manifest: Manifest,
+    /// reading order
+    pub spine: Spine,
+    /// table of contents of the navigation document or NCX, see `Parser::toc` for books
+    /// without one
     pub toc: Toc,
+    /// cover image path
+    pub cover: Option<String>,
+    /// global rendering properties, see `SpineItem::rendition` for the per page ones
+    pub rendition: Rendition,
 }
 
-impl Parser {
-    pub fn open(path: &str) -> Result<Parser> {
+impl Parser<File> {
+    pub fn open(path: &str) -> Result<Parser<File>> {
         let file = File::open(path)?;
-        let archive = ZipArchive::new(file)?;
 
-        Ok(Parser { archive })
+        Parser::from_reader(file)
+    }
+}
+
+impl<B: AsRef<[u8]>> Parser<Cursor<B>> {
+    /// Parse an EPUB held in memory, e.g. `Vec<u8>` or `&[u8]`.
+    pub fn from_bytes(bytes: B) -> Result<Parser<Cursor<B>>> {
+        Parser::from_reader(Cursor::new(bytes))
+    }
+}
+
+impl<R: Read + Seek> Parser<R> {
+    /// Parse an EPUB from any seekable source.
+    pub fn from_reader(reader: R) -> Result<Parser<R>> {
+        let mut archive = ZipArchive::new(reader).map_err(Error::Archive)?;
+        // a book without a readable container can still be validated
+        let container = read_text_file(&mut archive, CONTAINER_PATH)
+            .and_then(|container| Container::from(&container))
+            .map_err(share);
+
+        Ok(Parser {
+            archive,
+            container,
+            rendition: None,
+            package: None,
+            meta: None,
+            toc: None,
+            encryption: None,
+        })
     }
 
+    /// Metadata, manifest, spine and table of contents of the book. Parsed once, when first
+    /// needed, and again only after the rendition changes. A failure is kept too.
     pub fn meta(&mut self) -> Result<BookMeta> {
-        let container = read_text_file(&mut self.archive, CONTAINER_PATH)?;
-        let container = Container::from(&container)?;
-        if container.root_files.is_empty() {
-            return Err(anyhow!("no `rootfile` found"));
+        self.book_meta().cloned()
+    }
+
+    /// Table of contents of the book. Books without a navigation document or NCX, or with
+    /// empty ones, get one synthesized from the headings of their spine pages, read the first
+    /// time it's asked for.
+    pub fn toc(&mut self) -> Result<Toc> {
+        if self.toc.is_none() {
+            let meta = self.book_meta()?;
+            let (spine, toc) = (meta.spine.clone(), meta.toc.clone());
+            let toc = if toc.contents.is_empty() {
+                self.synthesize_toc(&spine, toc)
+            } else {
+                toc
+            };
+            self.toc = Some(toc);
         }
 
-        let root_file = &container.root_files[0];
-        let pkg_doc_path = &root_file.full_path;
-        let pkg_doc = read_text_file(&mut self.archive, pkg_doc_path)?;
-        let pkg_doc = PackageDocument::from(&pkg_doc, &root_file.base_path)?;
-
-        let toc = if pkg_doc.toc_nav_doc_path.is_some() {
-            let toc = read_text_file(&mut self.archive, &pkg_doc.toc_nav_doc_path.unwrap())?;
-            Toc::from_nav_doc(&toc, &root_file.base_path)?
-        } else if pkg_doc.toc_ncx_path.is_some() {
-            let toc = read_text_file(&mut self.archive, &pkg_doc.toc_ncx_path.unwrap())?;
-            Toc::from_ncx(&toc, &root_file.base_path)?
-        } else {
-            return Err(anyhow!("no toc found"));
+        Ok(self.toc.clone().unwrap_or_default())
+    }
+
+    /// Read the cover image, if the book has one.
+    ///
+    /// The cover is looked up through the EPUB 3 `cover-image` manifest property, the EPUB 2
+    /// `<meta name="cover">`, the EPUB 2 guide, and finally the first image on the first page.
+    pub fn cover(&mut self) -> Result<Option<Cover>> {
+        let meta = self.book_meta()?;
+        let Some(path) = meta.cover.clone() else {
+            return Ok(None);
+        };
+        let media_type = meta
+            .manifest
+            .by_path(&path)
+            .and_then(|item| item.media_type.clone())
+            .or_else(|| media_type::guess(&path));
+
+        let data = self.resource(&path)?;
+
+        Ok(Some(Cover {
+            path,
+            media_type,
+            data,
+        }))
+    }
+
+    /// All renditions declared in `container.xml`, the default rendition first.
+    pub fn renditions(&mut self) -> Result<Vec<RootFile>> {
+        Ok(self.container()?.renditions().cloned().collect())
+    }
+
+    /// Use `root_file` for `meta`, `cover` and all other package level lookups.
+    pub fn set_rendition(&mut self, root_file: &RootFile) {
+        self.rendition = Some(root_file.full_path.clone());
+        self.package = None;
+        self.meta = None;
+        self.toc = None;
+    }
+
+    /// Select the rendition best matching `selector` and use it from now on.
+    pub fn select_rendition(&mut self, selector: &RenditionSelector) -> Result<RootFile> {
+        let root_file = self
+            .container()?
+            .select(selector)
+            .cloned()
+            .ok_or(Error::NoRootFile)?;
+        self.set_rendition(&root_file);
+
+        Ok(root_file)
+    }
+
+    /// Edit the package document of the rendition in use, see [`Editor`].
+    pub fn edit(mut self) -> Result<Editor<R>> {
+        let (root_file, source) = self.package_document_source()?;
+        let pkg_doc = PackageDocument::from(&source, &root_file)?;
+        let (toc, toc_source) = match self.read_toc(&pkg_doc) {
+            Ok((toc, None)) => (self.synthesize_toc(&pkg_doc.spine, toc), None),
+            Ok(read) => read,
+            // metadata and manifest edits don't need a readable table of contents
+            Err(_) => Default::default(),
+        };
+
+        let package = Package {
+            path: root_file.full_path,
+            source,
+            metadata: pkg_doc.metadata,
+            manifest: pkg_doc.manifest,
+            toc,
+            toc_source,
+            ncx_path: pkg_doc.toc_ncx_path,
         };
 
+        Ok(Editor::new(self.archive, package))
+    }
+
+    /// Turn the parser into a [`Book`] that can be read from several threads at once. Fails
+    /// with the error `meta` failed with, no longer shared as `Error::Cached`.
+    pub fn into_book(mut self) -> Result<Book<R>>
+    where
+        R: Clone,
+    {
+        let _ = self.book_meta();
+        let encryption = self.encryption().cloned().unwrap_or_default();
+        // the other caches go, leaving the failure to this one
+        let Parser {
+            archive,
+            container,
+            package,
+            meta,
+            ..
+        } = self;
+        drop((container, package));
+        let meta = meta
+            .unwrap()
+            .map_err(|err| Arc::try_unwrap(err).unwrap_or_else(Error::Cached))?;
+
+        Ok(Book::new(archive, meta, encryption))
+    }
+
+    /// Check the book against the EPUB specification, see [`Report`]. Works on books `meta`
+    /// fails to parse, e.g. without a package document.
+    pub fn validate(&mut self) -> Report {
+        validator::validate(&mut self.archive)
+    }
+
+    /// DRM and encryption of the book, see [`Protection`]. Fails when `META-INF/encryption.xml`
+    /// can't be parsed, which other reads ignore.
+    pub fn protection(&mut self) -> Result<Protection> {
+        let encryption = self.encryption()?.clone();
+        let entries: Vec<String> = self
+            .archive
+            .file_names()
+            .map(|name| name.to_string())
+            .collect();
+        let rights = match read_text_file(&mut self.archive, RIGHTS_PATH) {
+            Ok(rights) => Some(rights),
+            Err(Error::MissingResource { .. }) => None,
+            Err(err) => return Err(err),
+        };
+
+        Ok(Protection::from(encryption, &entries, rights.as_deref()))
+    }
+
+    /// Read the resource at `path`. Fonts obfuscated as declared in `META-INF/encryption.xml`
+    /// are de-obfuscated, see `raw_resource` for the bytes as stored. Encrypted resources fail
+    /// with `Error::EncryptedResource`.
+    pub fn resource(&mut self, path: &str) -> Result<Vec<u8>> {
+        // an unreadable `encryption.xml` doesn't stop reads, `protection` reports it
+        let obfuscation = match self.encryption() {
+            Ok(encryption) => {
+                encryption.check(path)?;
+                encryption.obfuscation(path)
+            }
+            Err(_) => None,
+        };
+
+        let mut data = read_binary_file(&mut self.archive, path)?;
+        if let Some(obfuscation) = obfuscation {
+            obfuscation.deobfuscate(&mut data, &self.package_document()?.metadata);
+        }
+
+        Ok(data)
+    }
+
+    /// Read the resource of the manifest item `id`.
+    pub fn resource_by_id(&mut self, id: &str) -> Result<Vec<u8>> {
+        let path = self
+            .book_meta()?
+            .manifest
+            .by_id(id)
+            .map(|item| item.path.clone())
+            .ok_or_else(|| Error::MissingManifestItem { id: id.to_string() })?;
+
+        self.resource(&path)
+    }
+
+    /// Read the resource `href` refers to from the document at `doc_path`, e.g. an image of a
+    /// chapter. The fragment, if any, is ignored.
+    pub fn resource_by_href(&mut self, doc_path: &str, href: &str) -> Result<Vec<u8>> {
+        let href = Href::resolve(doc_path, href);
+
+        self.resource(&href.path)
+    }
+
+    /// Read the spine item at `index`, in reading order.
+    pub fn spine_resource(&mut self, index: usize) -> Result<Vec<u8>> {
+        let path = self
+            .book_meta()?
+            .spine
+            .items
+            .get(index)
+            .map(|item| item.path.clone())
+            .ok_or(Error::MissingSpineItem { index })?;
+
+        self.resource(&path)
+    }
+
+    /// Read the resource at `path` as stored in the archive.
+    pub fn raw_resource(&mut self, path: &str) -> Result<Vec<u8>> {
+        read_binary_file(&mut self.archive, path)
+    }
+
+    /// Readable text of the XHTML or SVG document at `path`, see [`TextOptions`].
+    pub fn text(&mut self, path: &str) -> Result<String> {
+        self.text_with_options(path, &TextOptions::default())
+    }
+
+    pub fn text_with_options(&mut self, path: &str, options: &TextOptions) -> Result<String> {
+        let doc = self.read_document(path)?;
+        text::extract(&doc, path, options)
+    }
+
+    /// Text of every spine item in reading order, `linear="no"` items included.
+    pub fn spine_text(&mut self, options: TextOptions) -> Result<SpineText<'_, R>> {
+        let paths = self
+            .book_meta()?
+            .spine
+            .paths()
+            .map(|path| path.to_string())
+            .collect();
+
+        Ok(SpineText::new(self, paths, options))
+    }
+
+    /// Search the text of every spine item, see [`SearchOptions`] for the defaults. Items that
+    /// can't be read, e.g. encrypted or malformed ones, are skipped.
+    pub fn search(&mut self, query: &str) -> Result<Vec<SearchMatch>> {
+        self.search_with_options(query, &SearchOptions::default())
+    }
+
+    pub fn search_with_options(
+        &mut self,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchMatch>> {
+        let query = search::Query::new(query, options)?;
+        let spine = self.book_meta()?.spine.clone();
+
+        let mut matches = Vec::new();
+        for (spine_index, path) in spine.paths().enumerate() {
+            let found = self
+                .read_document(path)
+                .and_then(|doc| query.find(&doc, &spine, spine_index));
+            matches.extend(found.into_iter().flatten());
+        }
+
+        Ok(matches)
+    }
+
+    fn container(&self) -> Result<&Container> {
+        cached(&self.container)
+    }
+
+    fn encryption(&mut self) -> Result<&Encryption> {
+        if self.encryption.is_none() {
+            let encryption = match read_text_file(&mut self.archive, ENCRYPTION_PATH) {
+                Ok(encryption) => Encryption::from(&encryption),
+                Err(Error::MissingResource { .. }) => Ok(Encryption::default()),
+                Err(err) => Err(err),
+            };
+            self.encryption = Some(encryption.map_err(share));
+        }
+
+        cached(self.encryption.as_ref().unwrap())
+    }
+
+    /// Read the XML document at `path`, failing with `Error::EncryptedResource` if encrypted.
+    fn read_document(&mut self, path: &str) -> Result<String> {
+        if let Ok(encryption) = self.encryption() {
+            encryption.check(path)?;
+        }
+        read_text_file(&mut self.archive, path)
+    }
+
+    fn book_meta(&mut self) -> Result<&BookMeta> {
+        if self.meta.is_none() {
+            self.meta = Some(self.parse_meta().map_err(share));
+        }
+
+        cached(self.meta.as_ref().unwrap())
+    }
+
+    fn parse_meta(&mut self) -> Result<BookMeta> {
+        let mut pkg_doc = self.package_document()?.clone();
+
+        let (toc, _) = self.read_toc(&pkg_doc)?;
+        let cover = self.find_cover(&pkg_doc);
+
+        // EPUB 2 books sometimes only name title and author in the NCX
+        let title = pkg_doc
+            .metadata
+            .title()
+            .filter(|title| !title.is_empty())
+            .or(toc.doc_title.as_deref())
+            .unwrap_or_default()
+            .to_string();
+        if pkg_doc.metadata.creators.is_empty() {
+            pkg_doc.metadata.creators = toc
+                .doc_authors
+                .iter()
+                .map(|author| Creator {
+                    name: author.clone(),
+                    ..Default::default()
+                })
+                .collect();
+        }
+
         Ok(BookMeta {
-            title: pkg_doc.title,
+            title,
+            metadata: pkg_doc.metadata,
             manifest: pkg_doc.manifest,
             spine: pkg_doc.spine,
             toc,
+            cover,
+            rendition: pkg_doc.rendition,
         })
     }
 
-    pub fn resource(&mut self, path: &str) -> Result<Vec<u8>> {
-        Ok(read_binary_file(&mut self.archive, path)?)
+    fn package_document(&mut self) -> Result<&PackageDocument> {
+        if self.package.is_none() {
+            self.package = Some(self.parse_package_document().map_err(share));
+        }
+
+        cached(self.package.as_ref().unwrap())
+    }
+
+    fn parse_package_document(&mut self) -> Result<PackageDocument> {
+        let (root_file, pkg_doc) = self.package_document_source()?;
+        let mut pkg_doc = PackageDocument::from(&pkg_doc, &root_file)?;
+
+        // iBooks fixed layout books may declare their layout only in Apple's display options
+        if let Ok(display_options) =
+            read_text_file(&mut self.archive, rendition::APPLE_DISPLAY_OPTIONS_PATH)
+        {
+            let apple = Rendition::from_apple_display_options(&display_options);
+            pkg_doc.rendition = pkg_doc.rendition.or(&apple);
+            pkg_doc.spine.resolve_rendition(&apple);
+        }
+
+        Ok(pkg_doc)
+    }
+
+    /// The rendition in use and its package document.
+    fn package_document_source(&mut self) -> Result<(RootFile, String)> {
+        let container = self.container()?;
+        let root_file = match &self.rendition {
+            Some(full_path) => container
+                .renditions()
+                .find(|root_file| &root_file.full_path == full_path)
+                .cloned()
+                .unwrap_or_else(|| RootFile {
+                    full_path: full_path.clone(),
+                    ..Default::default()
+                }),
+            None => container
+                .renditions()
+                .next()
+                .cloned()
+                .ok_or(Error::NoRootFile)?,
+        };
+
+        let pkg_doc =
+            read_text_file(&mut self.archive, &root_file.full_path).map_err(|err| match err {
+                Error::MissingResource { path } => Error::MissingPackageDocument { path },
+                err => err,
+            })?;
+
+        Ok((root_file, pkg_doc))
+    }
+
+    /// Read the navigation document, falling back to the NCX when the navigation document
+    /// is missing, malformed or has no usable table of contents. The navigation document's
+    /// landmarks and page list are kept either way. Also returns the document the table of
+    /// contents was read from, `None` if neither was usable.
+    fn read_toc(&mut self, pkg_doc: &PackageDocument) -> Result<(Toc, Option<TocSource>)> {
+        // landmarks and page list of a navigation document without a usable `toc` nav
+        let mut nav_doc_lists = None;
+        let mut encrypted = None;
+        if let Some(path) = &pkg_doc.toc_nav_doc_path {
+            match self.read_document(path) {
+                Ok(doc) => match Toc::from_nav_doc(&doc, path) {
+                    Ok(toc) if !toc.contents.is_empty() => {
+                        return Ok((toc, Some(TocSource::NavDoc(path.clone()))))
+                    }
+                    Ok(toc) => nav_doc_lists = Some(toc),
+                    Err(_) => nav_doc_lists = Toc::from_nav_doc_lists(&doc, path).ok(),
+                },
+                Err(err @ Error::EncryptedResource { .. }) => encrypted = Some(err),
+                // missing, like a malformed one, falls back to the NCX or the spine
+                Err(_) => {}
+            }
+        }
+
+        let ncx_toc = match &pkg_doc.toc_ncx_path {
+            Some(path) => match self
+                .read_document(path)
+                .and_then(|doc| Toc::from_ncx(&doc, path))
+            {
+                Ok(toc) => Some((toc, path)),
+                Err(err @ Error::EncryptedResource { .. }) => return Err(err),
+                Err(_) => None,
+            },
+            None => None,
+        };
+
+        let mut source = None;
+        let mut toc = match (ncx_toc, encrypted) {
+            (Some((toc, path)), _) => {
+                source = Some(TocSource::Ncx(path.clone()));
+                toc
+            }
+            (None, Some(err)) => return Err(err),
+            (None, None) => Toc::default(),
+        };
+        if let Some(lists) = nav_doc_lists {
+            if toc.landmarks.is_empty() {
+                toc.landmarks = lists.landmarks;
+            }
+            if toc.page_list.is_empty() {
+                toc.page_list = lists.page_list;
+            }
+        }
+
+        Ok((toc, source))
+    }
+
+    /// `toc` with contents synthesized from the headings of the linear spine pages.
+    fn synthesize_toc(&mut self, spine: &Spine, toc: Toc) -> Toc {
+        let pages = spine
+            .linear()
+            .filter_map(|item| {
+                let doc = self.read_document(&item.path).ok()?;
+                Some((item.path.clone(), doc))
+            })
+            .collect::<Vec<_>>();
+
+        Toc {
+            landmarks: toc.landmarks,
+            page_list: toc.page_list,
+            nav_lists: toc.nav_lists,
+            doc_title: toc.doc_title,
+            doc_authors: toc.doc_authors,
+            ..Toc::from_spine_pages(&pages)
+        }
+    }
+
+    fn find_cover(&mut self, pkg_doc: &PackageDocument) -> Option<String> {
+        if pkg_doc.cover_image_path.is_some() {
+            return pkg_doc.cover_image_path.clone();
+        }
+
+        let is_image = |path: &str| {
+            pkg_doc
+                .manifest
+                .by_path(path)
+                .and_then(|item| item.media_type.clone())
+                .or_else(|| media_type::guess(path))
+                .is_some_and(|media_type| media_type.starts_with("image/"))
+        };
+
+        let guide_cover = pkg_doc
+            .guide
+            .iter()
+            .find(|reference| reference.reference_type.eq_ignore_ascii_case("cover"))
+            .map(|reference| reference.path.as_str());
+        let pages = guide_cover.into_iter().chain(pkg_doc.spine.paths().next());
+
+        for page in pages {
+            if is_image(page) {
+                return Some(page.to_string());
+            }
+            let image = read_text_file(&mut self.archive, page)
+                .ok()
+                .and_then(|doc| cover::first_image(&doc, page));
+            if image.is_some() {
+                return image;
+            }
+        }
+
+        None
+    }
+}
+
+/// `err` to keep, a failure already kept by another lookup stays shared rather than wrapped
+fn share(err: Error) -> Arc<Error> {
+    match err {
+        Error::Cached(err) => err,
+        err => Arc::new(err),
+    }
+}
+
+fn cached<T>(result: &Cached<T>) -> Result<&T> {
+    result
+        .as_ref()
+        .map_err(|err| Error::Cached(Arc::clone(err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_util::{
+        build_zip, epub, sample_epub, with_items, CHAPTER, CONTAINER, NAV_DOC, PACKAGE_DOCUMENT,
+    };
+
+    #[test]
+    fn parse_from_bytes() {
+        let bytes = sample_epub();
+
+        let mut parser = Parser::from_bytes(bytes.as_slice()).unwrap();
+        let meta = parser.meta().unwrap();
+        assert_eq!("Sample Book", meta.title);
+        assert_eq!(
+            vec!["epub/chapter-1.xhtml"],
+            meta.spine.paths().collect::<Vec<_>>()
+        );
+
+        assert_eq!(None, meta.cover);
+
+        let resource = parser.resource("epub/chapter-1.xhtml").unwrap();
+        assert_eq!(CHAPTER.as_bytes(), resource.as_slice());
+
+        let pages = parser
+            .spine_text(TextOptions::default())
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            vec![PageText {
+                index: 0,
+                path: String::from("epub/chapter-1.xhtml"),
+                text: String::from("Chapter 1\nIt was a dark and stormy night."),
+            }],
+            pages
+        );
+    }
+
+    #[test]
+    fn report_typed_errors() {
+        let err = Parser::from_bytes(b"not a zip".as_slice()).unwrap_err();
+        assert!(matches!(err, Error::Archive(_)));
+
+        let bytes = build_zip(&[("META-INF/container.xml", CONTAINER.as_bytes())]);
+        let mut parser = Parser::from_bytes(bytes).unwrap();
+        let Error::Cached(err) = parser.meta().unwrap_err() else {
+            panic!("failure not cached");
+        };
+        assert!(
+            matches!(err.as_ref(), Error::MissingPackageDocument { path } if path == "epub/content.opf")
+        );
+        // the failure is kept, not parsed again
+        assert!(
+            matches!(parser.meta().unwrap_err(), Error::Cached(again) if Arc::ptr_eq(&err, &again))
+        );
+
+        let bytes = build_zip(&[
+            ("META-INF/container.xml", CONTAINER.as_bytes()),
+            ("epub/content.opf", b"<package><metadata></package>"),
+        ]);
+        let err = Parser::from_bytes(bytes).unwrap().meta().unwrap_err();
+        let source = std::error::Error::source(&err).unwrap();
+        assert!(
+            matches!(source.downcast_ref(), Some(Error::Xml { path, .. }) if path == "epub/content.opf")
+        );
+        // the XML error stays reachable through the source chain
+        assert!(source.source().unwrap().is::<roxmltree::Error>());
+
+        // a book without `container.xml` still opens, to be validated
+        let bytes = build_zip(&[("epub/content.opf", PACKAGE_DOCUMENT.as_bytes())]);
+        let mut parser = Parser::from_bytes(bytes).unwrap();
+        assert!(
+            matches!(parser.renditions().unwrap_err(), Error::Cached(err) if matches!(err.as_ref(), Error::MissingResource { path } if path == CONTAINER_PATH))
+        );
+        assert!(!parser.validate().is_valid());
+
+        let err = Parser::from_bytes(sample_epub())
+            .unwrap()
+            .resource("epub/missing.xhtml")
+            .unwrap_err();
+        assert!(matches!(err, Error::MissingResource { path } if path == "epub/missing.xhtml"));
+    }
+
+    #[test]
+    fn find_cover_through_guide() {
+        let pkg_doc = r#"<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="2.0">
+    <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+        <dc:title>Sample Book</dc:title>
+    </metadata>
+    <manifest>
+        <item href="cover.xhtml" id="cover" media-type="application/xhtml+xml"/>
+        <item href="images/cover.png" id="cover-png" media-type="image/png"/>
+        <item href="toc.ncx" id="ncx" media-type="application/x-dtbncx+xml"/>
+    </manifest>
+    <spine toc="ncx">
+        <itemref idref="cover"/>
+    </spine>
+    <guide>
+        <reference type="cover" title="Cover" href="cover.xhtml"/>
+    </guide>
+</package>"#;
+        let cover_page = r#"<html xmlns="http://www.w3.org/1999/xhtml"><body><img src="images/cover.png"/></body></html>"#;
+        let ncx = r#"<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/"><navMap/></ncx>"#;
+        let bytes = epub(
+            pkg_doc,
+            &[
+                ("epub/cover.xhtml", cover_page.as_bytes()),
+                ("epub/images/cover.png", b"\x89PNG"),
+                ("epub/toc.ncx", ncx.as_bytes()),
+            ],
+        );
+
+        let mut parser = Parser::from_bytes(bytes).unwrap();
+        assert_eq!(
+            Some(String::from("epub/images/cover.png")),
+            parser.meta().unwrap().cover
+        );
+        assert_eq!(
+            Some(Cover {
+                path: String::from("epub/images/cover.png"),
+                media_type: Some(String::from("image/png")),
+                data: b"\x89PNG".to_vec(),
+            }),
+            parser.cover().unwrap()
+        );
+    }
+
+    #[test]
+    fn resolve_paths_from_archive_root() {
+        let container = r#"<container xmlns="urn:oasis:names:tc:opendocument:xmlns:container" version="1.0">
+    <rootfiles>
+        <rootfile full-path="content.opf" media-type="application/oebps-package+xml"/>
+    </rootfiles>
+</container>"#;
+        let pkg_doc = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+    <metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title>Sample Book</dc:title></metadata>
+    <manifest>
+        <item href="text/Chapter%201.xhtml" id="chapter-1" media-type="application/xhtml+xml"/>
+        <item href="./nav/toc.xhtml" id="toc" media-type="application/xhtml+xml" properties="nav"/>
+    </manifest>
+    <spine>
+        <itemref idref="chapter-1"/>
+    </spine>
+</package>"#;
+        let nav_doc = r#"<html xmlns="http://www.w3.org/1999/xhtml"><body>
+<nav id="toc"><ol><li><a href="../text/Chapter%201.xhtml#start">Chapter 1</a></li></ol></nav>
+</body></html>"#;
+        let bytes = build_zip(&[
+            ("META-INF/container.xml", container.as_bytes()),
+            ("content.opf", pkg_doc.as_bytes()),
+            ("nav/toc.xhtml", nav_doc.as_bytes()),
+            ("text/Chapter 1.xhtml", CHAPTER.as_bytes()),
+        ]);
+
+        let mut parser = Parser::from_bytes(bytes).unwrap();
+        let meta = parser.meta().unwrap();
+        assert_eq!(
+            vec!["text/Chapter 1.xhtml"],
+            meta.spine.paths().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some(Href {
+                path: String::from("text/Chapter 1.xhtml"),
+                fragment: Some(String::from("start")),
+            }),
+            meta.toc.contents[0].href
+        );
+        assert!(parser.resource(&meta.spine.items[0].path).is_ok());
+    }
+
+    #[test]
+    fn fall_back_to_ncx() {
+        let pkg_doc = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+    <metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title>Sample Book</dc:title></metadata>
+    <manifest>
+        <item href="chapter-1.xhtml" id="chapter-1" media-type="application/xhtml+xml"/>
+        <item href="nav.xhtml" id="nav" media-type="application/xhtml+xml" properties="nav"/>
+        <item href="toc.ncx" id="toc-ncx" media-type="application/x-dtbncx+xml"/>
+    </manifest>
+    <spine toc="toc-ncx">
+        <itemref idref="chapter-1"/>
+    </spine>
+</package>"#;
+        let nav_doc = r#"<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<body><nav epub:type="landmarks"><ol><li><a epub:type="bodymatter" href="chapter-1.xhtml">Start</a></li></ol></nav></body>
+</html>"#;
+        let ncx = r#"<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/"><navMap>
+    <navPoint id="ch1"><navLabel><text>Chapter 1</text></navLabel><content src="chapter-1.xhtml"/></navPoint>
+</navMap></ncx>"#;
+        let bytes = epub(
+            pkg_doc,
+            &[
+                ("epub/nav.xhtml", nav_doc.as_bytes()),
+                ("epub/toc.ncx", ncx.as_bytes()),
+                ("epub/chapter-1.xhtml", CHAPTER.as_bytes()),
+            ],
+        );
+
+        let toc = Parser::from_bytes(bytes).unwrap().meta().unwrap().toc;
+        assert_eq!(1, toc.contents.len());
+        assert_eq!("Chapter 1", toc.contents[0].title);
+        assert_eq!(1, toc.landmarks.len());
+        assert_eq!("bodymatter", toc.landmarks[0].epub_type);
+
+        // a missing or broken navigation document without an NCX falls back to the spine
+        for nav_doc in [None, Some("<html><body>"), Some(nav_doc)] {
+            let mut entries = vec![("epub/chapter-1.xhtml", CHAPTER.as_bytes())];
+            entries.extend(nav_doc.map(|nav_doc| ("epub/toc.xhtml", nav_doc.as_bytes())));
+            let bytes = epub(PACKAGE_DOCUMENT, &entries);
+
+            let mut parser = Parser::from_bytes(bytes).unwrap();
+            assert!(parser.meta().unwrap().toc.contents.is_empty());
+            let toc = parser.toc().unwrap();
+            assert!(toc.synthesized);
+            assert_eq!("Chapter 1", toc.contents[0].title);
+        }
+    }
+
+    #[test]
+    fn resolve_fixed_layout() {
+        let pkg_doc = PACKAGE_DOCUMENT
+            .replace(
+                "</metadata>",
+                r#"<meta property="rendition:spread">none</meta></metadata>"#,
+            )
+            .replace(
+                r#"<itemref idref="chapter-1"/>"#,
+                r#"<itemref idref="chapter-1" properties="rendition:spread-landscape page-spread-left"/>"#,
+            );
+        let display_options = r#"<display_options><platform name="*">
+    <option name="fixed-layout">true</option>
+</platform></display_options>"#;
+        let bytes = epub(
+            &pkg_doc,
+            &[
+                (
+                    "META-INF/com.apple.ibooks.display-options.xml",
+                    display_options.as_bytes(),
+                ),
+                ("epub/toc.xhtml", NAV_DOC.as_bytes()),
+                ("epub/chapter-1.xhtml", CHAPTER.as_bytes()),
+            ],
+        );
+
+        let meta = Parser::from_bytes(bytes).unwrap().meta().unwrap();
+        assert_eq!(
+            Rendition {
+                layout: Some(Layout::PrePaginated),
+                spread: Some(Spread::None),
+                ..Default::default()
+            },
+            meta.rendition
+        );
+        let item = &meta.spine.items[0];
+        assert_eq!(Some(Spread::Landscape), item.rendition.spread);
+        assert!(item.rendition.is_pre_paginated());
+        assert_eq!(Some(PageSpread::Left), item.page_spread);
+    }
+
+    #[test]
+    fn open_selected_rendition() {
+        let container = r#"<container xmlns="urn:oasis:names:tc:opendocument:xmlns:container"
+           xmlns:rendition="http://www.idpf.org/2013/rendition" version="1.0">
+    <rootfiles>
+        <rootfile full-path="epub/content.opf" media-type="application/oebps-package+xml"/>
+        <rootfile full-path="ja/content.opf" media-type="application/oebps-package+xml"
+                  rendition:language="ja"/>
+    </rootfiles>
+</container>"#;
+        let ja_pkg_doc = PACKAGE_DOCUMENT
+            .replace("Sample Book", "サンプル")
+            .replace("chapter-1.xhtml", "ja-1.xhtml");
+        let bytes = build_zip(&[
+            ("META-INF/container.xml", container.as_bytes()),
+            ("epub/content.opf", PACKAGE_DOCUMENT.as_bytes()),
+            ("epub/toc.xhtml", NAV_DOC.as_bytes()),
+            ("ja/content.opf", ja_pkg_doc.as_bytes()),
+            ("ja/toc.xhtml", NAV_DOC.as_bytes()),
+        ]);
+
+        let mut parser = Parser::from_bytes(bytes).unwrap();
+        assert_eq!(2, parser.renditions().unwrap().len());
+        assert_eq!("Sample Book", parser.meta().unwrap().title);
+
+        let selector = RenditionSelector {
+            language: Some(String::from("ja")),
+            ..Default::default()
+        };
+        let root_file = parser.select_rendition(&selector).unwrap();
+        assert_eq!("ja/content.opf", root_file.full_path);
+        let meta = parser.meta().unwrap();
+        assert_eq!("サンプル", meta.title);
+        assert_eq!(
+            vec!["ja/ja-1.xhtml"],
+            meta.spine.paths().collect::<Vec<_>>()
+        );
+
+        // a rendition missing from the archive fails to load, without a fallback
+        parser.set_rendition(&RootFile {
+            full_path: String::from("fr/content.opf"),
+            ..Default::default()
+        });
+        assert!(matches!(
+            parser.meta().unwrap_err(),
+            Error::Cached(err) if matches!(err.as_ref(), Error::MissingPackageDocument { path } if path == "fr/content.opf")
+        ));
+
+        // only package documents count as renditions
+        let container = r#"<container xmlns="urn:oasis:names:tc:opendocument:xmlns:container" version="1.0">
+    <rootfiles>
+        <rootfile full-path="book.pdf" media-type="application/pdf"/>
+    </rootfiles>
+</container>"#;
+        let bytes = build_zip(&[("META-INF/container.xml", container.as_bytes())]);
+        let mut parser = Parser::from_bytes(bytes).unwrap();
+        assert!(parser.renditions().unwrap().is_empty());
+        assert!(matches!(
+            parser.select_rendition(&RenditionSelector::default()),
+            Err(Error::NoRootFile)
+        ));
+        assert!(matches!(
+            parser.meta().unwrap_err(),
+            Error::Cached(err) if matches!(err.as_ref(), Error::NoRootFile)
+        ));
+    }
+
+    #[test]
+    fn deobfuscate_fonts() {
+        let encryption = r#"<encryption xmlns="urn:oasis:names:tc:opendocument:xmlns:container"
+            xmlns:enc="http://www.w3.org/2001/04/xmlenc#">
+    <enc:EncryptedData>
+        <enc:EncryptionMethod Algorithm="http://www.idpf.org/2008/embedding"/>
+        <enc:CipherData><enc:CipherReference URI="epub/font.otf"/></enc:CipherData>
+    </enc:EncryptedData>
+</encryption>"#;
+        let font: Vec<u8> = (0..2000).map(|i| (i % 251) as u8).collect();
+        let metadata = Metadata {
+            unique_identifier: Some(String::from(
+                "urn:uuid:d3a1e1f2-8a55-4b5b-9f0e-3c7e9d3c2b10",
+            )),
+            ..Default::default()
+        };
+        let mut obfuscated = font.clone();
+        encryption::Obfuscation::Idpf.deobfuscate(&mut obfuscated, &metadata);
+        assert_ne!(font[..1040], obfuscated[..1040]);
+        assert_eq!(font[1040..], obfuscated[1040..]);
+
+        let bytes = epub(
+            PACKAGE_DOCUMENT,
+            &[
+                ("META-INF/encryption.xml", encryption.as_bytes()),
+                ("epub/font.otf", &obfuscated),
+            ],
+        );
+
+        let mut parser = Parser::from_bytes(bytes).unwrap();
+        assert_eq!(font, parser.resource("epub/font.otf").unwrap());
+        assert_eq!(obfuscated, parser.raw_resource("epub/font.otf").unwrap());
+    }
+
+    #[test]
+    fn reject_encrypted_resources() {
+        let encryption = r#"<encryption xmlns="urn:oasis:names:tc:opendocument:xmlns:container"
+            xmlns:enc="http://www.w3.org/2001/04/xmlenc#">
+    <enc:EncryptedData>
+        <enc:EncryptionMethod Algorithm="http://www.w3.org/2001/04/xmlenc#aes256-cbc"/>
+        <enc:CipherData><enc:CipherReference URI="epub/toc.xhtml"/></enc:CipherData>
+    </enc:EncryptedData>
+</encryption>"#;
+        let bytes = epub(
+            PACKAGE_DOCUMENT,
+            &[
+                ("META-INF/encryption.xml", encryption.as_bytes()),
+                ("epub/toc.xhtml", b"\x8f\x02\xc4"),
+            ],
+        );
+
+        let mut parser = Parser::from_bytes(bytes).unwrap();
+        let protection = parser.protection().unwrap();
+        assert_eq!(vec![Drm::Unknown], protection.drm);
+        assert!(matches!(
+            parser.meta().unwrap_err(),
+            Error::Cached(err) if matches!(err.as_ref(), Error::EncryptedResource { path, .. } if path == "epub/toc.xhtml")
+        ));
+        assert!(matches!(
+            parser.resource("epub/toc.xhtml").unwrap_err(),
+            Error::EncryptedResource { .. }
+        ));
+        assert_eq!(
+            b"\x8f\x02\xc4",
+            parser.raw_resource("epub/toc.xhtml").unwrap().as_slice()
+        );
+
+        let bytes = epub(
+            PACKAGE_DOCUMENT,
+            &[
+                ("META-INF/encryption.xml", b"<encryption>"),
+                ("epub/toc.xhtml", NAV_DOC.as_bytes()),
+                ("epub/chapter-1.xhtml", CHAPTER.as_bytes()),
+            ],
+        );
+        let mut parser = Parser::from_bytes(bytes).unwrap();
+        assert_eq!("Sample Book", parser.meta().unwrap().title);
+        assert!(parser.resource("epub/chapter-1.xhtml").is_ok());
+        assert!(matches!(
+            parser.protection().unwrap_err(),
+            Error::Cached(err) if matches!(err.as_ref(), Error::Xml { path, .. } if path == "META-INF/encryption.xml")
+        ));
+    }
+
+    #[test]
+    fn search_readable_pages() {
+        let pkg_doc = with_items(
+            PACKAGE_DOCUMENT,
+            &[r#"<item href="broken.xhtml" id="broken" media-type="application/xhtml+xml"/>"#],
+        )
+        .replace("<spine>", r#"<spine><itemref idref="broken"/>"#);
+        let bytes = epub(
+            &pkg_doc,
+            &[
+                ("epub/broken.xhtml", b"<html><body><p>stormy night"),
+                ("epub/chapter-1.xhtml", CHAPTER.as_bytes()),
+            ],
+        );
+
+        let matches = Parser::from_bytes(bytes)
+            .unwrap()
+            .search("stormy night")
+            .unwrap();
+        assert_eq!(1, matches.len());
+        assert_eq!("epub/chapter-1.xhtml", matches[0].path);
+    }
+
+    #[test]
+    fn look_up_resources() {
+        let mut parser = Parser::from_bytes(sample_epub()).unwrap();
+        assert!(parser.meta.is_none());
+
+        assert_eq!(
+            CHAPTER.as_bytes(),
+            parser.resource_by_id("chapter-1").unwrap().as_slice()
+        );
+        assert!(matches!(parser.meta, Some(Ok(_))));
+        assert_eq!(
+            CHAPTER.as_bytes(),
+            parser.spine_resource(0).unwrap().as_slice()
+        );
+        assert_eq!(
+            CHAPTER.as_bytes(),
+            parser
+                .resource_by_href("epub/toc.xhtml", "chapter-1.xhtml#start")
+                .unwrap()
+                .as_slice()
+        );
+        assert!(matches!(
+            parser.resource_by_id("missing").unwrap_err(),
+            Error::MissingManifestItem { id } if id == "missing"
+        ));
+        assert!(matches!(
+            parser.spine_resource(1).unwrap_err(),
+            Error::MissingSpineItem { index: 1 }
+        ));
+
+        // an encoded `#` belongs to the file name, only a literal one starts the fragment
+        let bytes = epub(
+            PACKAGE_DOCUMENT,
+            &[("epub/notes#1.xhtml", CHAPTER.as_bytes())],
+        );
+        let mut parser = Parser::from_bytes(bytes).unwrap();
+        assert_eq!(
+            CHAPTER.as_bytes(),
+            parser
+                .resource_by_href("epub/chapter-1.xhtml", "notes%231.xhtml#note-1")
+                .unwrap()
+                .as_slice()
+        );
+        assert_eq!(
+            Href {
+                path: String::from("epub/notes#1.xhtml"),
+                fragment: Some(String::from("note-1")),
+            },
+            Href::resolve("epub/chapter-1.xhtml", "notes%231.xhtml#note-1")
+        );
     }
 }
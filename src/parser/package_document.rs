@@ -1,165 +1,137 @@
-use std::collections::HashMap;
-
-use anyhow::{anyhow, Result};
 use roxmltree::Node;
 
-const NAMESPACE_DC: &str = "http://purl.org/dc/elements/1.1/";
+use crate::error::{Error, Result};
+use crate::parser::container::RootFile;
+use crate::parser::manifest::Manifest;
+use crate::parser::metadata::Metadata;
+use crate::parser::rendition::{Rendition, Viewport};
+use crate::parser::spine::Spine;
+use crate::parser::toc::Href;
+use crate::util;
+use crate::util::xml_util;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub(crate) struct PackageDocument {
-    pub title: String,
-    pub language: String,
+    pub metadata: Metadata,
     pub cover_image_path: Option<String>,
-    pub spine: Vec<String>,
-    pub manifest: HashMap<String, Option<String>>,
+    pub spine: Spine,
+    pub manifest: Manifest,
     pub toc_ncx_path: Option<String>,
     pub toc_nav_doc_path: Option<String>,
+    pub guide: Vec<GuideReference>,
+    /// global rendering properties, already applied to the spine items
+    pub rendition: Rendition,
+}
+
+/// EPUB 2 `guide/reference`
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct GuideReference {
+    pub reference_type: String,
+    pub title: Option<String>,
+    pub path: String,
 }
 
 impl PackageDocument {
-    pub fn from(doc: &str, base_path: &str) -> Result<PackageDocument> {
-        let doc = roxmltree::Document::parse(doc)?;
+    pub fn from(doc: &str, root_file: &RootFile) -> Result<PackageDocument> {
+        let path = &root_file.full_path;
+        let doc = xml_util::parse(doc, path)?;
         let package_elem = doc.root_element();
 
         let metadata_elem = package_elem
             .children()
             .find(|node| node.has_tag_name("metadata"))
-            .ok_or(anyhow!("`metadata` node not found"))?;
+            .ok_or_else(|| Error::missing_element(path, "metadata"))?;
 
-        let (title, language) = Self::parse_metadata(&metadata_elem);
+        let metadata = Metadata::from(&metadata_elem, package_elem.attribute("unique-identifier"));
 
         let manifest_elem = package_elem
             .children()
             .find(|node| node.has_tag_name("manifest"))
-            .ok_or(anyhow!("`manifest` node not found"))?;
+            .ok_or_else(|| Error::missing_element(path, "manifest"))?;
 
-        let (cover_image_path, toc_nav_doc_path, manifest_by_id, manifest_by_path) =
-            Self::parse_manifest(&manifest_elem, base_path);
+        let manifest = Manifest::from(&manifest_elem, path);
+        let toc_nav_doc_path = manifest.by_property("nav").map(|item| item.path.clone());
+        // EPUB 2 declares the cover through `<meta name="cover" content="id">`, and some books
+        // put the image href in `content` instead of the id
+        let cover_image_path = manifest
+            .by_property("cover-image")
+            .or_else(|| {
+                let content = &metadata
+                    .metas
+                    .iter()
+                    .find(|meta| meta.refines.is_none() && meta.property == "cover")?
+                    .value;
+                manifest
+                    .by_id(content)
+                    .or_else(|| manifest.by_path(&util::path::resolve(path, content)))
+            })
+            .map(|item| item.path.clone());
 
         let spine_elem = package_elem
             .children()
             .find(|node| node.has_tag_name("spine"))
-            .ok_or(anyhow!("`spine` node not found"))?;
+            .ok_or_else(|| Error::missing_element(path, "spine"))?;
+
+        let rendition = Rendition::from_metas(&metadata.metas);
+        let mut spine = Spine::from(&spine_elem, &manifest);
+        // EPUB 3.0.1 lets `rendition:viewport` refine a single manifest item
+        for item in &mut spine.items {
+            if item.rendition.viewport.is_none() {
+                item.rendition.viewport = metadata
+                    .metas
+                    .iter()
+                    .filter(|meta| meta.property == "rendition:viewport")
+                    .find(|meta| meta.refines.as_deref() == Some(item.idref.as_str()))
+                    .and_then(|meta| Viewport::parse(&meta.value));
+            }
+        }
+        spine.resolve_rendition(&rendition);
+        let toc_ncx_path = spine
+            .toc
+            .as_ref()
+            .and_then(|ncx| manifest.by_id(ncx))
+            .map(|item| item.path.clone());
 
-        let (ncx, spine) = Self::parse_spine(&spine_elem, &manifest_by_id);
-        let toc_ncx_path = ncx.map(|ncx| manifest_by_id.get(&ncx).cloned()).flatten();
+        let guide = package_elem
+            .children()
+            .find(|node| node.has_tag_name("guide"))
+            .map(|guide_elem| Self::parse_guide(&guide_elem, path))
+            .unwrap_or_default();
 
         Ok(PackageDocument {
-            title,
-            language,
+            metadata,
             cover_image_path,
             spine,
-            manifest: manifest_by_path,
+            manifest,
             toc_ncx_path,
             toc_nav_doc_path,
+            guide,
+            rendition,
         })
     }
 
-    fn parse_metadata(metadata_elem: &Node) -> (String, String) {
-        let title = metadata_elem
-            .children()
-            .find(|node| node.has_tag_name((NAMESPACE_DC, "title")))
-            .map(|node| node.text().unwrap_or_default().trim())
-            .unwrap_or_default()
-            .to_string();
-
-        let language = metadata_elem
-            .children()
-            .find(|node| node.has_tag_name((NAMESPACE_DC, "language")))
-            .map(|node| node.text().unwrap_or_default().trim())
-            .unwrap_or_default()
-            .to_string();
-
-        (title, language)
-    }
-
-    fn parse_manifest(
-        manifest_elem: &Node,
-        base_path: &str,
-    ) -> (
-        Option<String>,
-        Option<String>,
-        HashMap<String, String>,
-        HashMap<String, Option<String>>,
-    ) {
-        let mut manifest_by_id = HashMap::new();
-        let mut manifest_by_path = HashMap::new();
-        let mut cover_image_path = None;
-        let mut toc_nav_doc_path = None;
-
-        for node in manifest_elem
-            .children()
-            .filter(|node| node.has_tag_name("item"))
-        {
-            if node.attribute("properties") == Some("cover-image") {
-                cover_image_path = node
-                    .attribute("href")
-                    .map(|str| format!("{}/{}", base_path, str));
-            }
-
-            if node.attribute("properties") == Some("nav") {
-                toc_nav_doc_path = node
-                    .attribute("href")
-                    .map(|str| format!("{}/{}", base_path, str));
-            }
-
-            if node.has_attribute("id") && node.has_attribute("href") {
-                manifest_by_id.insert(
-                    node.attribute("id").unwrap().to_string(),
-                    format!("{}/{}", base_path, node.attribute("href").unwrap()),
-                );
-
-                manifest_by_path.insert(
-                    format!("{}/{}", base_path, node.attribute("href").unwrap()),
-                    node.attribute("media-type").map(|str| str.to_string()),
-                );
-            }
-        }
-
-        (
-            cover_image_path,
-            toc_nav_doc_path,
-            manifest_by_id,
-            manifest_by_path,
-        )
-    }
-
-    fn parse_spine(
-        spine_elem: &Node,
-        manifest_by_id: &HashMap<String, String>,
-    ) -> (Option<String>, Vec<String>) {
-        let ncx = spine_elem
-            .attribute("toc")
-            .map(|toc| {
-                if toc == "ncx" {
-                    Some(String::from("ncx"))
-                } else {
-                    None
-                }
-            })
-            .flatten();
-        let spine = spine_elem
+    fn parse_guide(guide_elem: &Node, doc_path: &str) -> Vec<GuideReference> {
+        guide_elem
             .children()
-            .filter(|node| {
-                node.has_tag_name("itemref")
-                    && node.has_attribute("idref")
-                    && manifest_by_id.contains_key(node.attribute("idref").unwrap())
-            })
-            .map(|node| {
-                manifest_by_id
-                    .get(&node.attribute("idref").unwrap().to_string())
-                    .unwrap()
-                    .to_owned()
+            .filter(|node| node.has_tag_name("reference"))
+            .filter_map(|node| {
+                Some(GuideReference {
+                    reference_type: node.attribute("type")?.to_string(),
+                    title: node.attribute("title").map(|str| str.to_string()),
+                    // guide references may carry a fragment, e.g. `cover.xhtml#start`
+                    path: Href::resolve(doc_path, node.attribute("href")?).path,
+                })
             })
-            .collect();
-
-        (ncx, spine)
+            .collect()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parser::manifest::ManifestItem;
+    use crate::parser::metadata::{Identifier, Title};
+    use crate::parser::spine::SpineItem;
 
     #[test]
     fn parse_package_document() {
@@ -192,52 +164,147 @@ mod tests {
         "###.trim();
 
         let base_path = "epub";
+        let item = |id: &str, href: &str, media_type: &str, properties: &[&str]| ManifestItem {
+            id: id.to_string(),
+            href: href.to_string(),
+            path: format!("{}/{}", base_path, href),
+            media_type: Some(media_type.to_string()),
+            properties: properties.iter().map(|str| str.to_string()).collect(),
+            ..Default::default()
+        };
 
         let expected = PackageDocument {
-            title: "Jane Eyre".to_string(),
-            language: "en-GB".to_string(),
-            cover_image_path: Some(format!("{}/{}", base_path, "images/cover.svg")),
-            spine: vec![
-                format!("{}/{}", base_path, "text/preface.xhtml"),
-                format!("{}/{}", base_path, "text/chapter-1.xhtml"),
-                format!("{}/{}", base_path, "text/endnotes.xhtml"),
-            ],
-            manifest: HashMap::from([
-                (
-                    format!("{}/{}", base_path, "css/core.css"),
-                    Some(String::from("text/css")),
-                ),
-                (
-                    format!("{}/{}", base_path, "images/cover.svg"),
-                    Some(String::from("image/svg+xml")),
-                ),
-                (
-                    format!("{}/{}", base_path, "text/chapter-1.xhtml"),
-                    Some(String::from("application/xhtml+xml")),
+            metadata: Metadata {
+                unique_identifier: Some(
+                    "url:https://standardebooks.org/ebooks/charlotte-bronte/jane-eyre".to_string(),
                 ),
-                (
-                    format!("{}/{}", base_path, "text/endnotes.xhtml"),
-                    Some(String::from("application/xhtml+xml")),
-                ),
-                (
-                    format!("{}/{}", base_path, "text/preface.xhtml"),
-                    Some(String::from("application/xhtml+xml")),
-                ),
-                (
-                    format!("{}/{}", base_path, "toc.xhtml"),
-                    Some(String::from("application/xhtml+xml")),
-                ),
-                (
-                    format!("{}/{}", base_path, "toc.ncx"),
-                    Some(String::from("application/x-dtbncx+xml")),
-                ),
-            ]),
+                identifiers: vec![Identifier {
+                    id: Some("uid".to_string()),
+                    value: "url:https://standardebooks.org/ebooks/charlotte-bronte/jane-eyre"
+                        .to_string(),
+                    scheme: None,
+                }],
+                titles: vec![
+                    Title {
+                        id: Some("title".to_string()),
+                        value: "Jane Eyre".to_string(),
+                        ..Default::default()
+                    },
+                    Title {
+                        id: Some("subtitle".to_string()),
+                        value: "An Autobiography".to_string(),
+                        ..Default::default()
+                    },
+                    Title {
+                        id: Some("fulltitle".to_string()),
+                        value: "Jane Eyre: An Autobiography".to_string(),
+                        ..Default::default()
+                    },
+                ],
+                languages: vec!["en-GB".to_string()],
+                ..Default::default()
+            },
+            cover_image_path: Some(format!("{}/{}", base_path, "images/cover.svg")),
+            spine: Spine {
+                items: ["preface.xhtml", "chapter-1.xhtml", "endnotes.xhtml"]
+                    .iter()
+                    .map(|id| SpineItem {
+                        id: None,
+                        idref: id.to_string(),
+                        path: format!("{}/text/{}", base_path, id),
+                        linear: true,
+                        ..Default::default()
+                    })
+                    .collect(),
+                toc: Some("ncx".to_string()),
+                ..Default::default()
+            },
+            manifest: Manifest {
+                items: vec![
+                    item("core.css", "css/core.css", "text/css", &[]),
+                    item(
+                        "cover.svg",
+                        "images/cover.svg",
+                        "image/svg+xml",
+                        &["cover-image"],
+                    ),
+                    item(
+                        "chapter-1.xhtml",
+                        "text/chapter-1.xhtml",
+                        "application/xhtml+xml",
+                        &[],
+                    ),
+                    item(
+                        "endnotes.xhtml",
+                        "text/endnotes.xhtml",
+                        "application/xhtml+xml",
+                        &[],
+                    ),
+                    item(
+                        "preface.xhtml",
+                        "text/preface.xhtml",
+                        "application/xhtml+xml",
+                        &[],
+                    ),
+                    item("toc.xhtml", "toc.xhtml", "application/xhtml+xml", &["nav"]),
+                    item("ncx", "toc.ncx", "application/x-dtbncx+xml", &[]),
+                ],
+            },
             toc_ncx_path: Some(format!("{}/{}", base_path, "toc.ncx")),
             toc_nav_doc_path: Some(format!("{}/{}", base_path, "toc.xhtml")),
+            guide: vec![],
+            rendition: Rendition::default(),
         };
 
-        let parsed = PackageDocument::from(&doc, base_path).unwrap();
+        let root_file = RootFile {
+            full_path: format!("{}/{}", base_path, "content.opf"),
+            ..Default::default()
+        };
+        let parsed = PackageDocument::from(doc, &root_file).unwrap();
 
         assert_eq!(expected, parsed)
     }
+
+    #[test]
+    fn parse_legacy_cover() {
+        let doc = r###"
+<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="2.0">
+    <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+        <dc:title>Jane Eyre</dc:title>
+        <meta name="cover" content="cover-jpg"/>
+    </metadata>
+    <manifest>
+        <item href="images/cover.jpg" id="cover-jpg" media-type="image/jpeg"/>
+        <item href="text/cover.xhtml" id="cover" media-type="application/xhtml+xml"/>
+    </manifest>
+    <spine>
+        <itemref idref="cover"/>
+    </spine>
+    <guide>
+        <reference type="cover" title="Cover" href="text/cover.xhtml"/>
+    </guide>
+</package>
+        "###
+        .trim();
+
+        let root_file = RootFile {
+            full_path: "epub/content.opf".to_string(),
+            ..Default::default()
+        };
+        let parsed = PackageDocument::from(doc, &root_file).unwrap();
+
+        assert_eq!(
+            Some("epub/images/cover.jpg"),
+            parsed.cover_image_path.as_deref()
+        );
+        assert_eq!(
+            vec![GuideReference {
+                reference_type: "cover".to_string(),
+                title: Some("Cover".to_string()),
+                path: "epub/text/cover.xhtml".to_string(),
+            }],
+            parsed.guide
+        );
+    }
 }
@@ -0,0 +1,128 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::parser::encryption::{EncryptedResource, Encryption, NAMESPACE_ADEPT};
+
+pub(crate) const RIGHTS_PATH: &str = "META-INF/rights.xml";
+const LCP_LICENSE_PATH: &str = "META-INF/license.lcpl";
+const FAIRPLAY_PATH: &str = "META-INF/sinf.xml";
+
+/// DRM and encryption of a book, see `Parser::protection`
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Protection {
+    /// DRM schemes found
+    pub drm: Vec<Drm>,
+    /// encrypted resources and their algorithm
+    pub encrypted: Vec<EncryptedResource>,
+    /// obfuscated fonts, read as usual by `Parser::resource`
+    pub obfuscated: Vec<EncryptedResource>,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Drm {
+    /// a `META-INF/license.lcpl` license or content keys retrieved from one
+    ReadiumLcp,
+    /// Adobe ADEPT `rights.xml` or key info
+    AdobeAdept,
+    /// a `META-INF/sinf.xml` FairPlay license
+    AppleFairPlay,
+    /// encryption or a `rights.xml` of no known scheme
+    Unknown,
+}
+
+impl Protection {
+    /// `entries` are the names of the archive entries, `rights` the content of `rights.xml`.
+    pub(crate) fn from(
+        encryption: Encryption,
+        entries: &[String],
+        rights: Option<&str>,
+    ) -> Protection {
+        let (obfuscated, encrypted): (Vec<_>, Vec<_>) = encryption
+            .resources
+            .into_iter()
+            .partition(|resource| resource.is_obfuscated());
+
+        let mut drm = encryption.drm;
+        if entries.iter().any(|entry| entry == LCP_LICENSE_PATH) {
+            drm.push(Drm::ReadiumLcp);
+        }
+        if let Some(rights) = rights {
+            match rights.contains(NAMESPACE_ADEPT) {
+                true => drm.push(Drm::AdobeAdept),
+                false => drm.push(Drm::Unknown),
+            }
+        }
+        if entries.iter().any(|entry| entry == FAIRPLAY_PATH) {
+            drm.push(Drm::AppleFairPlay);
+        }
+        if drm.is_empty() && !encrypted.is_empty() {
+            drm.push(Drm::Unknown);
+        }
+        drm.sort();
+        drm.dedup();
+
+        Protection {
+            drm,
+            encrypted,
+            obfuscated,
+        }
+    }
+
+    /// Whether the book has DRM or encrypted resources. Obfuscated fonts don't count.
+    pub fn is_protected(&self) -> bool {
+        !self.drm.is_empty() || !self.encrypted.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_drm() {
+        let encryption = r#"<?xml version="1.0" encoding="utf-8"?>
+<encryption xmlns="urn:oasis:names:tc:opendocument:xmlns:container"
+            xmlns:enc="http://www.w3.org/2001/04/xmlenc#"
+            xmlns:ds="http://www.w3.org/2000/09/xmldsig#">
+    <enc:EncryptedData>
+        <enc:EncryptionMethod Algorithm="http://www.w3.org/2001/04/xmlenc#aes256-cbc"/>
+        <ds:KeyInfo>
+            <ds:RetrievalMethod URI="license.lcpl#/encryption/content_key"
+                                Type="http://readium.org/2014/01/lcp#EncryptedContentKey"/>
+        </ds:KeyInfo>
+        <enc:CipherData><enc:CipherReference URI="epub/chapter-1.xhtml"/></enc:CipherData>
+    </enc:EncryptedData>
+    <enc:EncryptedData>
+        <enc:EncryptionMethod Algorithm="http://www.idpf.org/2008/embedding"/>
+        <enc:CipherData><enc:CipherReference URI="epub/font.otf"/></enc:CipherData>
+    </enc:EncryptedData>
+</encryption>"#;
+        let encryption = Encryption::from(encryption).unwrap();
+        let entries = ["mimetype", "META-INF/license.lcpl", "epub/chapter-1.xhtml"]
+            .map(|entry| entry.to_string());
+
+        let protection = Protection::from(encryption, &entries, None);
+        assert!(protection.is_protected());
+        assert_eq!(vec![Drm::ReadiumLcp], protection.drm);
+        assert_eq!(
+            vec![EncryptedResource {
+                path: String::from("epub/chapter-1.xhtml"),
+                algorithm: String::from("http://www.w3.org/2001/04/xmlenc#aes256-cbc"),
+            }],
+            protection.encrypted
+        );
+        assert_eq!("epub/font.otf", protection.obfuscated[0].path);
+
+        let rights = r#"<adept:rights xmlns:adept="http://ns.adobe.com/adept"/>"#;
+        let protection = Protection::from(
+            Encryption::default(),
+            &["META-INF/rights.xml", "META-INF/sinf.xml"].map(|entry| entry.to_string()),
+            Some(rights),
+        );
+        assert_eq!(vec![Drm::AdobeAdept, Drm::AppleFairPlay], protection.drm);
+
+        assert!(!Protection::from(Encryption::default(), &entries[..1], None).is_protected());
+    }
+}
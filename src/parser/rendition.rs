@@ -0,0 +1,296 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::parser::metadata::Meta;
+
+pub(crate) const APPLE_DISPLAY_OPTIONS_PATH: &str = "META-INF/com.apple.ibooks.display-options.xml";
+
+/// rendering properties of the book or of a single spine item, `None` where undeclared
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rendition {
+    /// `rendition:layout`
+    pub layout: Option<Layout>,
+    /// `rendition:orientation`
+    pub orientation: Option<Orientation>,
+    /// `rendition:spread`
+    pub spread: Option<Spread>,
+    /// `rendition:flow`
+    pub flow: Option<Flow>,
+    /// `rendition:viewport`, deprecated in favor of the XHTML `viewport` meta
+    pub viewport: Option<Viewport>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Layout {
+    Reflowable,
+    /// fixed layout
+    PrePaginated,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Orientation {
+    Auto,
+    Landscape,
+    Portrait,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Spread {
+    None,
+    Landscape,
+    /// deprecated, same as `Both`
+    Portrait,
+    Both,
+    Auto,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Flow {
+    Paginated,
+    ScrolledContinuous,
+    ScrolledDoc,
+    Auto,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Viewport {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// side of a spread a fixed layout page is placed on
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PageSpread {
+    Left,
+    Right,
+    /// `rendition:page-spread-center`, centered across both sides
+    Center,
+}
+
+impl Rendition {
+    /// Global rendering properties from the package `meta` elements.
+    pub(crate) fn from_metas(metas: &[Meta]) -> Rendition {
+        let mut rendition = Rendition::default();
+        for meta in metas.iter().filter(|meta| meta.refines.is_none()) {
+            rendition.set(&meta.property, meta.value.trim());
+        }
+
+        rendition
+    }
+
+    /// Overrides declared as itemref properties, e.g. `rendition:layout-pre-paginated`.
+    pub(crate) fn from_properties(properties: &[String]) -> Rendition {
+        let mut rendition = Rendition::default();
+        for property in properties {
+            if let Some((name, value)) = ["layout", "orientation", "spread", "flow"]
+                .iter()
+                .find_map(|name| {
+                    let value = property
+                        .strip_prefix("rendition:")?
+                        .strip_prefix(name)?
+                        .strip_prefix('-')?;
+                    Some((format!("rendition:{}", name), value))
+                })
+            {
+                rendition.set(&name, value);
+            }
+        }
+
+        rendition
+    }
+
+    /// Properties from Apple's `com.apple.ibooks.display-options.xml`.
+    pub(crate) fn from_apple_display_options(doc: &str) -> Rendition {
+        let mut rendition = Rendition::default();
+        let Ok(doc) = roxmltree::Document::parse(doc) else {
+            return rendition;
+        };
+
+        // options for every platform (`*`) come first, device specific ones are ignored
+        let platform = doc
+            .descendants()
+            .filter(|node| node.has_tag_name("platform"))
+            .find(|node| node.attribute("name") == Some("*"))
+            .or_else(|| doc.descendants().find(|node| node.has_tag_name("platform")));
+        for option in platform
+            .iter()
+            .flat_map(|platform| platform.children())
+            .filter(|node| node.has_tag_name("option"))
+        {
+            let value = option.text().unwrap_or_default().trim();
+            match option.attribute("name") {
+                Some("fixed-layout") if value == "true" => {
+                    rendition.layout = Some(Layout::PrePaginated)
+                }
+                Some("orientation-lock") => {
+                    rendition.orientation = match value {
+                        "landscape-only" => Some(Orientation::Landscape),
+                        "portrait-only" => Some(Orientation::Portrait),
+                        "none" => Some(Orientation::Auto),
+                        _ => None,
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        rendition
+    }
+
+    fn set(&mut self, property: &str, value: &str) {
+        match property {
+            "rendition:layout" => {
+                self.layout = match value {
+                    "reflowable" => Some(Layout::Reflowable),
+                    "pre-paginated" => Some(Layout::PrePaginated),
+                    _ => None,
+                }
+            }
+            "rendition:orientation" => {
+                self.orientation = match value {
+                    "auto" => Some(Orientation::Auto),
+                    "landscape" => Some(Orientation::Landscape),
+                    "portrait" => Some(Orientation::Portrait),
+                    _ => None,
+                }
+            }
+            "rendition:spread" => {
+                self.spread = match value {
+                    "none" => Some(Spread::None),
+                    "landscape" => Some(Spread::Landscape),
+                    "portrait" => Some(Spread::Portrait),
+                    "both" => Some(Spread::Both),
+                    "auto" => Some(Spread::Auto),
+                    _ => None,
+                }
+            }
+            "rendition:flow" => {
+                self.flow = match value {
+                    "paginated" => Some(Flow::Paginated),
+                    "scrolled-continuous" => Some(Flow::ScrolledContinuous),
+                    "scrolled-doc" => Some(Flow::ScrolledDoc),
+                    "auto" => Some(Flow::Auto),
+                    _ => None,
+                }
+            }
+            "rendition:viewport" => self.viewport = Viewport::parse(value),
+            _ => {}
+        }
+    }
+
+    /// Fill the properties undeclared here from `fallback`.
+    pub fn or(&self, fallback: &Rendition) -> Rendition {
+        Rendition {
+            layout: self.layout.or(fallback.layout),
+            orientation: self.orientation.or(fallback.orientation),
+            spread: self.spread.or(fallback.spread),
+            flow: self.flow.or(fallback.flow),
+            viewport: self.viewport.or(fallback.viewport),
+        }
+    }
+
+    pub fn is_pre_paginated(&self) -> bool {
+        self.layout == Some(Layout::PrePaginated)
+    }
+}
+
+impl Viewport {
+    /// Parse `width=1200, height=1600`.
+    pub(crate) fn parse(value: &str) -> Option<Viewport> {
+        let mut width = None;
+        let mut height = None;
+        for pair in value.split([',', ';']) {
+            let Some((name, value)) = pair.split_once('=') else {
+                continue;
+            };
+            match name.trim() {
+                "width" => width = value.trim().parse().ok(),
+                "height" => height = value.trim().parse().ok(),
+                _ => {}
+            }
+        }
+
+        Some(Viewport {
+            width: width?,
+            height: height?,
+        })
+    }
+}
+
+impl PageSpread {
+    pub(crate) fn from_properties(properties: &[String]) -> Option<PageSpread> {
+        properties
+            .iter()
+            .find_map(|property| match property.as_str() {
+                "page-spread-left" | "rendition:page-spread-left" => Some(PageSpread::Left),
+                "page-spread-right" | "rendition:page-spread-right" => Some(PageSpread::Right),
+                "rendition:page-spread-center" => Some(PageSpread::Center),
+                _ => None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_rendition() {
+        let metas = [
+            ("rendition:layout", "pre-paginated"),
+            ("rendition:spread", "landscape"),
+            ("rendition:viewport", "width=1200, height=1600"),
+        ]
+        .map(|(property, value)| Meta {
+            property: property.to_string(),
+            value: value.to_string(),
+            ..Default::default()
+        });
+        let global = Rendition::from_metas(&metas);
+
+        let properties = [
+            "page-spread-right",
+            "rendition:spread-none",
+            "rendition:layout-reflowable",
+        ]
+        .map(|str| str.to_string());
+        let item = Rendition::from_properties(&properties).or(&global);
+
+        assert_eq!(
+            Rendition {
+                layout: Some(Layout::Reflowable),
+                orientation: None,
+                spread: Some(Spread::None),
+                flow: None,
+                viewport: Some(Viewport {
+                    width: 1200,
+                    height: 1600,
+                }),
+            },
+            item
+        );
+        assert_eq!(
+            Some(PageSpread::Right),
+            PageSpread::from_properties(&properties)
+        );
+
+        let apple = Rendition::from_apple_display_options(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<display_options>
+    <platform name="*">
+        <option name="fixed-layout">true</option>
+        <option name="orientation-lock">landscape-only</option>
+    </platform>
+</display_options>"#,
+        );
+        assert!(apple.is_pre_paginated());
+        assert_eq!(Some(Orientation::Landscape), apple.orientation);
+    }
+}
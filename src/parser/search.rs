@@ -0,0 +1,328 @@
+use std::ops::Range;
+
+use regex::{Regex, RegexBuilder};
+use roxmltree::{Document, Node};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use unicode_normalization::char::decompose_compatible;
+
+use crate::cfi::Cfi;
+use crate::error::{Error, Result};
+use crate::parser::text::{TextOptions, Writer};
+use crate::parser::Spine;
+use crate::util::xml_util;
+
+/// characters of context on each side of a match
+const SNIPPET_CONTEXT: usize = 40;
+
+/// How `Parser::search` matches its query
+#[derive(Debug, PartialEq, Clone)]
+pub struct SearchOptions {
+    /// match the query as a `regex` pattern instead of literally
+    pub regex: bool,
+    pub case_sensitive: bool,
+    /// Compare compatibility decompositions (NFKD) of literal queries: `é` matches `e` followed
+    /// by a combining acute accent and `ﬁ` matches `fi`. Regex patterns and the text they're
+    /// matched against are left as they are, decomposing would change classes such as `[é]`.
+    pub normalize: bool,
+}
+
+/// a match of `Parser::search`
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SearchMatch {
+    /// index in the spine
+    pub spine_index: usize,
+    /// resource path
+    pub path: String,
+    /// element the match starts in, e.g. `/html/body/section/p[2]`. Indexes count same-name
+    /// siblings from 1 and are left out for only children.
+    pub element_path: String,
+    /// location of the match start
+    pub cfi: Cfi,
+    /// text around the match, on a single line
+    pub snippet: String,
+    /// byte range of the match in `snippet`
+    pub highlight: Range<usize>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            regex: false,
+            case_sensitive: false,
+            normalize: true,
+        }
+    }
+}
+
+/// a compiled search query
+pub(crate) struct Query {
+    /// `None` for a query that can't match anything, e.g. only whitespace
+    regex: Option<Regex>,
+    /// how the searched text is folded to match the pattern
+    options: SearchOptions,
+}
+
+impl Query {
+    pub(crate) fn new(query: &str, options: &SearchOptions) -> Result<Query> {
+        let (pattern, fold_options) = match options.regex {
+            // lowercasing would turn escapes such as `\S` into `\s`, case is left to the regex
+            true => (
+                query.to_string(),
+                SearchOptions {
+                    case_sensitive: true,
+                    normalize: false,
+                    ..options.clone()
+                },
+            ),
+            false => (
+                regex::escape(fold(query, options).0.trim()),
+                options.clone(),
+            ),
+        };
+        let regex = match pattern.is_empty() {
+            true => None,
+            false => Some(
+                RegexBuilder::new(&pattern)
+                    .case_insensitive(!options.case_sensitive)
+                    .build()
+                    .map_err(Error::InvalidQuery)?,
+            ),
+        };
+
+        Ok(Query {
+            regex,
+            options: fold_options,
+        })
+    }
+
+    /// Matches in the text of `doc`, the document of the spine item at `spine_index`, in
+    /// document order.
+    pub(crate) fn find(
+        &self,
+        doc: &str,
+        spine: &Spine,
+        spine_index: usize,
+    ) -> Result<Vec<SearchMatch>> {
+        let Some(regex) = &self.regex else {
+            return Ok(Vec::new());
+        };
+        let path = spine
+            .items
+            .get(spine_index)
+            .map(|item| item.path.as_str())
+            .unwrap_or_default();
+        let doc = xml_util::replace_html_entities(doc);
+        let doc = xml_util::parse(&doc, path)?;
+        let writer = Writer::new(&doc.root_element(), &TextOptions::default());
+        let (folded, offsets) = fold(&writer.text, &self.options);
+
+        let matches = regex
+            .find_iter(&folded)
+            .filter(|found| !found.is_empty())
+            .map(|found| {
+                let range = offsets[found.start()]..offsets[found.end()];
+                let (snippet, highlight) = snippet(&writer.text, &range);
+                let (element_path, cfi) = match locate(&doc, &writer, range.start) {
+                    Some((node, offset)) => (
+                        element_path(&node),
+                        Cfi::generate(spine, spine_index, &node, Some(offset)),
+                    ),
+                    None => (String::new(), None),
+                };
+                SearchMatch {
+                    spine_index,
+                    path: path.to_string(),
+                    element_path,
+                    cfi: cfi.unwrap_or_default(),
+                    snippet,
+                    highlight,
+                }
+            })
+            .collect();
+
+        Ok(matches)
+    }
+}
+
+/// Lowercase and decompose `text` as `options` ask. Returns the folded text and, for each of
+/// its bytes plus its end, the byte offset in `text` it comes from.
+fn fold(text: &str, options: &SearchOptions) -> (String, Vec<usize>) {
+    let mut folded = String::with_capacity(text.len());
+    let mut offsets = Vec::with_capacity(text.len() + 1);
+    let mut push = |c: char, offset: usize| {
+        let mut push_char = |c: char| {
+            folded.push(c);
+            offsets.resize(folded.len(), offset);
+        };
+        match options.case_sensitive {
+            true => push_char(c),
+            false => c.to_lowercase().for_each(push_char),
+        }
+    };
+    for (offset, c) in text.char_indices() {
+        match options.normalize {
+            true => decompose_compatible(c, |c| push(c, offset)),
+            false => push(c, offset),
+        }
+    }
+    offsets.push(text.len());
+
+    (folded, offsets)
+}
+
+/// `range` of `text` with some context, and the range's position in it. The context ends
+/// at whole words.
+fn snippet(text: &str, range: &Range<usize>) -> (String, Range<usize>) {
+    let start = text[..range.start]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_CONTEXT - 1)
+        .map_or(0, |(i, _)| i);
+    let start = text[..start]
+        .rfind(char::is_whitespace)
+        .map_or(0, |i| i + 1);
+    let end = text[range.end..]
+        .char_indices()
+        .nth(SNIPPET_CONTEXT)
+        .map_or(text.len(), |(i, _)| range.end + i);
+    let end = text[end..]
+        .find(char::is_whitespace)
+        .map_or(text.len(), |i| end + i);
+
+    let snippet = text[start..end].replace('\n', " ");
+    (snippet, range.start - start..range.end - start)
+}
+
+/// text node and byte offset in it of byte `offset` of the writer's text
+fn locate<'a, 'input>(
+    doc: &'a Document<'input>,
+    writer: &Writer,
+    offset: usize,
+) -> Option<(Node<'a, 'input>, usize)> {
+    let span = writer
+        .spans
+        .iter()
+        .take_while(|span| span.start <= offset)
+        .last()?;
+    let node = doc.get_node(span.node)?;
+    // a match starting on collapsed whitespace starts at the end of the previous word
+    let offset = (span.offset + offset - span.start).min(node.text().unwrap_or_default().len());
+
+    Some((node, offset))
+}
+
+/// path of the element holding `node`
+fn element_path(node: &Node) -> String {
+    let mut steps: Vec<String> = node
+        .ancestors()
+        .filter(|node| node.is_element())
+        .map(|node| {
+            let name = node.tag_name().name();
+            let same_name = |sibling: &Node| sibling.tag_name().name() == name;
+            let index = node.prev_siblings().filter(same_name).count();
+            let count = index + node.next_siblings().skip(1).filter(same_name).count();
+            match count {
+                1 => name.to_string(),
+                _ => format!("{}[{}]", name, index),
+            }
+        })
+        .collect();
+    steps.reverse();
+
+    format!("/{}", steps.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::SpineItem;
+
+    #[test]
+    fn search_page() {
+        let doc = r#"<?xml version="1.0" encoding="utf-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head><title>Chapter 1</title></head>
+<body>
+    <h1>Chapter 1</h1>
+    <p>It was a <em>dark</em> and stormy night.</p>
+    <p>The Cafe\u{301} was DARK and empty.</p>
+</body>
+</html>"#
+            .replace("\\u{301}", "\u{301}");
+        let spine = Spine {
+            items: ["cover", "preface", "chapter-1"]
+                .iter()
+                .map(|id| SpineItem {
+                    idref: id.to_string(),
+                    path: format!("epub/{}.xhtml", id),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        let query = Query::new("café", &SearchOptions::default()).unwrap();
+        let matches = query.find(&doc, &spine, 2).unwrap();
+        assert_eq!(1, matches.len());
+        assert_eq!(2, matches[0].spine_index);
+        assert_eq!("/html/body/p[2]", matches[0].element_path);
+        assert_eq!("epubcfi(/6/6!/4/6/1:4)", matches[0].cfi.to_string());
+        assert_eq!(
+            "Cafe\u{301}",
+            &matches[0].snippet[matches[0].highlight.clone()]
+        );
+
+        let query = Query::new("dark", &SearchOptions::default()).unwrap();
+        let matches = query.find(&doc, &spine, 2).unwrap();
+        assert_eq!(
+            vec!["/html/body/p[1]/em", "/html/body/p[2]"],
+            matches
+                .iter()
+                .map(|found| found.element_path.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "Chapter 1 It was a dark and stormy night. The Cafe\u{301} was DARK and",
+            matches[0].snippet
+        );
+
+        let options = SearchOptions {
+            regex: true,
+            case_sensitive: true,
+            ..Default::default()
+        };
+        let query = Query::new(r"[A-Z]{4}\b", &options).unwrap();
+        let matches = query.find(&doc, &spine, 2).unwrap();
+        assert_eq!(1, matches.len());
+        assert_eq!("DARK", &matches[0].snippet[matches[0].highlight.clone()]);
+
+        let options = SearchOptions {
+            regex: true,
+            ..Default::default()
+        };
+        let query = Query::new(r"(STORMY|night)\S", &options).unwrap();
+        let matches = query.find(&doc, &spine, 2).unwrap();
+        assert_eq!(1, matches.len());
+        assert_eq!("night.", &matches[0].snippet[matches[0].highlight.clone()]);
+        // a decomposed class would be `[e\u{301}]`, matching every `e`
+        let query = Query::new("[é]", &options).unwrap();
+        assert!(query.find(&doc, &spine, 2).unwrap().is_empty());
+        let query = Query::new("Cafe\u{301}", &options).unwrap();
+        assert_eq!(1, query.find(&doc, &spine, 2).unwrap().len());
+
+        assert!(matches!(
+            Query::new("(dark", &options),
+            Err(Error::InvalidQuery(_))
+        ));
+        // only whitespace matches nothing rather than every gap between words
+        let query = Query::new(" \t", &SearchOptions::default()).unwrap();
+        assert!(query.find(&doc, &spine, 2).unwrap().is_empty());
+        let query = Query::new("dark", &SearchOptions::default()).unwrap();
+        assert!(matches!(
+            query.find("<html><body>", &spine, 0),
+            Err(Error::Xml { path, .. }) if path == "epub/cover.xhtml"
+        ));
+    }
+}
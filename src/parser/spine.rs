@@ -0,0 +1,192 @@
+use roxmltree::Node;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::parser::manifest::Manifest;
+use crate::parser::rendition::{PageSpread, Rendition};
+
+/// reading order of the book
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Spine {
+    pub items: Vec<SpineItem>,
+    pub page_progression_direction: PageProgressionDirection,
+    /// id of the NCX manifest item
+    pub toc: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SpineItem {
+    pub id: Option<String>,
+    /// id of the referenced manifest item
+    pub idref: String,
+    /// resource path of the referenced manifest item
+    pub path: String,
+    /// `false` for auxiliary content outside the main reading flow (`linear="no"`)
+    pub linear: bool,
+    /// e.g. `page-spread-left`, `page-spread-right` or `rendition:layout-pre-paginated`
+    pub properties: Vec<String>,
+    /// rendering properties of this item, overrides resolved against the book's
+    pub rendition: Rendition,
+    /// `page-spread-left`, `page-spread-right` or `rendition:page-spread-center`
+    pub page_spread: Option<PageSpread>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PageProgressionDirection {
+    Ltr,
+    Rtl,
+    /// left to the reading system
+    #[default]
+    Default,
+}
+
+impl Spine {
+    /// Itemrefs pointing to a missing manifest item are skipped.
+    pub(crate) fn from(spine_elem: &Node, manifest: &Manifest) -> Spine {
+        let items = spine_elem
+            .children()
+            .filter(|node| node.has_tag_name("itemref"))
+            .filter_map(|node| {
+                let item = manifest.by_id(node.attribute("idref")?)?;
+                let properties: Vec<String> = node
+                    .attribute("properties")
+                    .map(|properties| {
+                        properties
+                            .split_ascii_whitespace()
+                            .map(|str| str.to_string())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                Some(SpineItem {
+                    id: node.attribute("id").map(|str| str.to_string()),
+                    idref: item.id.clone(),
+                    path: item.path.clone(),
+                    linear: node.attribute("linear").map(|str| str.trim()) != Some("no"),
+                    // only the overrides for now, see `Spine::resolve_rendition`
+                    rendition: Rendition::from_properties(&properties),
+                    page_spread: PageSpread::from_properties(&properties),
+                    properties,
+                })
+            })
+            .collect();
+
+        let page_progression_direction = match spine_elem.attribute("page-progression-direction") {
+            Some("ltr") => PageProgressionDirection::Ltr,
+            Some("rtl") => PageProgressionDirection::Rtl,
+            _ => PageProgressionDirection::Default,
+        };
+
+        Spine {
+            items,
+            page_progression_direction,
+            toc: spine_elem.attribute("toc").map(|str| str.to_string()),
+        }
+    }
+
+    /// Fill the rendering properties the items don't override from `rendition`.
+    pub(crate) fn resolve_rendition(&mut self, rendition: &Rendition) {
+        for item in &mut self.items {
+            item.rendition = item.rendition.or(rendition);
+        }
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, SpineItem> {
+        self.items.iter()
+    }
+
+    /// items of the main reading flow, skipping `linear="no"`
+    pub fn linear(&self) -> impl Iterator<Item = &SpineItem> {
+        self.items.iter().filter(|item| item.linear)
+    }
+
+    /// index of the first item with the given resource path
+    pub fn position(&self, path: &str) -> Option<usize> {
+        self.items.iter().position(|item| item.path == path)
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.items.iter().map(|item| item.path.as_str())
+    }
+}
+
+impl SpineItem {
+    pub fn has_property(&self, property: &str) -> bool {
+        self.properties.iter().any(|p| p == property)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_spine() {
+        let doc = r#"
+<package xmlns="http://www.idpf.org/2007/opf">
+    <manifest>
+        <item id="cover" href="cover.xhtml" media-type="application/xhtml+xml"/>
+        <item id="page-1" href="page-1.xhtml" media-type="application/xhtml+xml"/>
+        <item id="page-2" href="page-2.xhtml" media-type="application/xhtml+xml"/>
+        <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+    </manifest>
+    <spine toc="ncx" page-progression-direction="rtl">
+        <itemref idref="cover" linear="no"/>
+        <itemref idref="page-1" id="itemref-1" properties="page-spread-right"/>
+        <itemref idref="missing"/>
+        <itemref idref="page-2" properties="page-spread-left"/>
+    </spine>
+</package>"#
+            .trim();
+
+        let doc = roxmltree::Document::parse(doc).unwrap();
+        let manifest_elem = doc.root_element().first_element_child().unwrap();
+        let spine_elem = doc.root_element().last_element_child().unwrap();
+        let manifest = Manifest::from(&manifest_elem, "epub/content.opf");
+
+        let expected = Spine {
+            items: vec![
+                SpineItem {
+                    id: None,
+                    idref: String::from("cover"),
+                    path: String::from("epub/cover.xhtml"),
+                    linear: false,
+                    ..Default::default()
+                },
+                SpineItem {
+                    id: Some(String::from("itemref-1")),
+                    idref: String::from("page-1"),
+                    path: String::from("epub/page-1.xhtml"),
+                    linear: true,
+                    properties: vec![String::from("page-spread-right")],
+                    page_spread: Some(PageSpread::Right),
+                    ..Default::default()
+                },
+                SpineItem {
+                    id: None,
+                    idref: String::from("page-2"),
+                    path: String::from("epub/page-2.xhtml"),
+                    linear: true,
+                    properties: vec![String::from("page-spread-left")],
+                    page_spread: Some(PageSpread::Left),
+                    ..Default::default()
+                },
+            ],
+            page_progression_direction: PageProgressionDirection::Rtl,
+            toc: Some(String::from("ncx")),
+        };
+
+        let parsed = Spine::from(&spine_elem, &manifest);
+
+        assert_eq!(expected, parsed);
+        assert_eq!(
+            vec!["epub/page-1.xhtml", "epub/page-2.xhtml"],
+            parsed
+                .linear()
+                .map(|item| item.path.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use std::io::{Read, Seek};
+
+use roxmltree::{Node, NodeId};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::parser::toc::NAMESPACE_OPS;
+use crate::parser::Parser;
+use crate::util::xml_util;
+
+/// elements starting and ending a line
+const BLOCK_ELEMENTS: [&str; 36] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "caption",
+    "dd",
+    "details",
+    "dialog",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "tr",
+    "ul",
+];
+
+/// elements whose content is never read out
+const SKIPPED_ELEMENTS: [&str; 6] = ["head", "script", "style", "template", "noscript", "rp"];
+
+/// `epub:type` and `role` values of footnote bodies
+const FOOTNOTE_TYPES: [&str; 10] = [
+    "footnote",
+    "footnotes",
+    "endnote",
+    "endnotes",
+    "rearnote",
+    "rearnotes",
+    "note",
+    "doc-footnote",
+    "doc-endnote",
+    "doc-endnotes",
+];
+
+/// How `Parser::text` turns a content document into plain text
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct TextOptions {
+    /// drop footnote and endnote bodies, marked by `epub:type` or a DPUB-ARIA `role`
+    pub skip_footnotes: bool,
+}
+
+/// plain text of a spine item, see `Parser::spine_text`
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PageText {
+    /// index in the spine
+    pub index: usize,
+    /// resource path
+    pub path: String,
+    pub text: String,
+}
+
+/// Iterator over the plain text of the spine items, in reading order
+#[derive(Debug)]
+pub struct SpineText<'a, R: Read + Seek> {
+    parser: &'a mut Parser<R>,
+    paths: std::iter::Enumerate<std::vec::IntoIter<String>>,
+    options: TextOptions,
+}
+
+impl<'a, R: Read + Seek> SpineText<'a, R> {
+    pub(crate) fn new(
+        parser: &'a mut Parser<R>,
+        paths: Vec<String>,
+        options: TextOptions,
+    ) -> SpineText<'a, R> {
+        SpineText {
+            parser,
+            paths: paths.into_iter().enumerate(),
+            options,
+        }
+    }
+}
+
+impl<R: Read + Seek> Iterator for SpineText<'_, R> {
+    type Item = Result<PageText>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (index, path) = self.paths.next()?;
+        let text = self.parser.text_with_options(&path, &self.options);
+
+        Some(text.map(|text| PageText { index, path, text }))
+    }
+}
+
+/// Extract the readable text of the XHTML or SVG document at `path`.
+///
+/// Block elements and `br` end a line and whitespace is collapsed elsewhere, `pre` keeps its
+/// own. HTML entities the XML parser doesn't know, e.g. `&nbsp;`, are decoded.
+pub(crate) fn extract(doc: &str, path: &str, options: &TextOptions) -> Result<String> {
+    let doc = xml_util::replace_html_entities(doc);
+    let doc = xml_util::parse(&doc, path)?;
+
+    Ok(Writer::new(&doc.root_element(), options).text)
+}
+
+/// text of a content document along with where each piece of it comes from
+#[derive(Default)]
+pub(crate) struct Writer {
+    pub text: String,
+    /// in text order
+    pub spans: Vec<Span>,
+    /// whitespace seen since the last word
+    space: bool,
+}
+
+/// text copied from a text node
+pub(crate) struct Span {
+    /// byte offset in `Writer::text`
+    pub start: usize,
+    pub node: NodeId,
+    /// byte offset in the text node
+    pub offset: usize,
+}
+
+impl Writer {
+    /// Write the `body` of the document `root` is the root element of, or all of it if it
+    /// has none, e.g. an SVG document.
+    pub(crate) fn new(root: &Node, options: &TextOptions) -> Writer {
+        let body = root
+            .children()
+            .find(|node| node.has_tag_name("body"))
+            .unwrap_or(*root);
+
+        let mut writer = Writer::default();
+        writer.write(&body, options, false);
+        writer.text.truncate(writer.text.trim_end().len());
+
+        writer
+    }
+
+    fn write(&mut self, node: &Node, options: &TextOptions, pre: bool) {
+        if node.is_text() {
+            let text = node.text().unwrap_or_default();
+            if pre {
+                self.push(text, node, 0);
+                self.space = false;
+            } else {
+                self.words(text, node);
+            }
+            return;
+        }
+        if !node.is_element() {
+            return;
+        }
+
+        let name = node.tag_name().name();
+        if SKIPPED_ELEMENTS.contains(&name) || (options.skip_footnotes && is_footnote(node)) {
+            return;
+        }
+        match name {
+            "br" => {
+                self.text.push('\n');
+                self.space = false;
+            }
+            // table cells share the row's line
+            "td" | "th" => {
+                self.space = true;
+                for child in node.children() {
+                    self.write(&child, options, pre);
+                }
+                self.space = true;
+            }
+            _ if BLOCK_ELEMENTS.contains(&name) => {
+                self.line_break();
+                for child in node.children() {
+                    self.write(&child, options, pre || name == "pre");
+                }
+                self.line_break();
+            }
+            _ => {
+                for child in node.children() {
+                    self.write(&child, options, pre);
+                }
+            }
+        }
+    }
+
+    /// Write `text` with its whitespace collapsed. Only XML whitespace counts, `&nbsp;` stays.
+    fn words(&mut self, text: &str, node: &Node) {
+        self.space |= text.starts_with(|c: char| c.is_ascii_whitespace());
+        let mut words = text.split_ascii_whitespace().peekable();
+        if words.peek().is_none() {
+            return;
+        }
+        for word in words {
+            if self.space && !self.text.is_empty() && !self.text.ends_with('\n') {
+                self.text.push(' ');
+            }
+            let offset = word.as_ptr() as usize - text.as_ptr() as usize;
+            self.push(word, node, offset);
+            self.space = true;
+        }
+        self.space = text.ends_with(|c: char| c.is_ascii_whitespace());
+    }
+
+    fn push(&mut self, text: &str, node: &Node, offset: usize) {
+        self.spans.push(Span {
+            start: self.text.len(),
+            node: node.id(),
+            offset,
+        });
+        self.text.push_str(text);
+    }
+
+    fn line_break(&mut self) {
+        if !self.text.is_empty() && !self.text.ends_with('\n') {
+            self.text.push('\n');
+        }
+        self.space = false;
+    }
+}
+
+fn is_footnote(node: &Node) -> bool {
+    [
+        node.attribute((NAMESPACE_OPS, "type")),
+        node.attribute("role"),
+    ]
+    .into_iter()
+    .flatten()
+    .flat_map(|types| types.split_ascii_whitespace())
+    .any(|value| FOOTNOTE_TYPES.contains(&value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_text() {
+        let doc = r##"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.1//EN" "http://www.w3.org/TR/xhtml11/DTD/xhtml11.dtd">
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+    <title>Chapter 1</title>
+    <style>p { text-indent: 1em; }</style>
+</head>
+<body>
+    <h1>Chapter&nbsp;1</h1>
+    <p>It was a <em>dark</em>
+        and stormy night<a epub:type="noteref" href="#n1">1</a>.</p>
+    <script>document.write("no");</script>
+    <p>Line one<br/>line two &mdash; &amp; more</p>
+    <pre>  keep
+    this</pre>
+    <table><tr><td>a</td><td>b</td></tr></table>
+    <aside epub:type="footnote" id="n1"><p>A footnote.</p></aside>
+</body>
+</html>"##;
+
+        assert_eq!(
+            "Chapter\u{a0}1\nIt was a dark and stormy night1.\nLine one\nline two \u{2014} & more\n  keep\n    this\na b\nA footnote.",
+            extract(doc, "epub/chapter-1.xhtml", &TextOptions::default()).unwrap()
+        );
+
+        let options = TextOptions {
+            skip_footnotes: true,
+        };
+        assert!(!extract(doc, "epub/chapter-1.xhtml", &options)
+            .unwrap()
+            .contains("A footnote."));
+    }
+}
@@ -1,66 +1,265 @@
-use anyhow::{anyhow, Result};
+use std::fmt::{Display, Formatter};
+
 use regex::Regex;
 use roxmltree::Node;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, PartialEq, Clone)]
+use crate::error::{Error, Result};
+use crate::parser::spine::Spine;
+use crate::util::{path, xml_util};
+
+pub(crate) const NAMESPACE_OPS: &str = "http://www.idpf.org/2007/ops";
+
+#[derive(Debug, PartialEq, Clone, Default)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Toc {
     pub contents: Vec<TocNode>,
+    /// structural landmarks, e.g. `cover`, `bodymatter` or `bibliography`
+    pub landmarks: Vec<Landmark>,
+    /// locations of the print edition's pages
+    pub page_list: Vec<PageTarget>,
+    /// NCX `navList`s, e.g. lists of illustrations or tables
+    pub nav_lists: Vec<NavList>,
+    /// NCX `docTitle`
+    pub doc_title: Option<String>,
+    /// NCX `docAuthor`s
+    pub doc_authors: Vec<String>,
+    /// `true` when the book has no table of contents and `contents` was built from the
+    /// spine pages' headings
+    pub synthesized: bool,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+/// the document a table of contents was read from
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub(crate) enum TocSource {
+    /// navigation document path
+    NavDoc(String),
+    /// NCX path
+    Ncx(String),
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TocNode {
     pub title: String,
-    pub href: Option<String>,
+    pub href: Option<Href>,
     pub children: Option<Vec<TocNode>>,
+    pub id: Option<String>,
+    /// NCX `class`, e.g. `chapter` or `h1`
+    pub class: Option<String>,
+    /// NCX `playOrder`
+    pub play_order: Option<u32>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Landmark {
+    /// `epub:type` of the link, e.g. `toc` or `bodymatter`
+    pub epub_type: String,
+    pub title: String,
+    pub href: Href,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PageTarget {
+    /// page number as printed, e.g. `iv` or `12`
+    pub label: String,
+    pub href: Href,
+    pub id: Option<String>,
+    /// NCX `type`: `front`, `normal` or `special`
+    pub page_type: Option<String>,
+    /// NCX `value`, the page number as an integer
+    pub value: Option<u32>,
+    /// NCX `playOrder`
+    pub play_order: Option<u32>,
+}
+
+/// NCX `navList`
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NavList {
+    pub id: Option<String>,
+    pub class: Option<String>,
+    pub title: String,
+    /// `navTarget`s
+    pub targets: Vec<TocNode>,
+}
+
+/// link target: a resource path plus an optional fragment identifier
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Href {
+    /// resource path, as accepted by `Parser::resource`
+    pub path: String,
+    /// fragment identifier without the leading `#`, e.g. an element id
+    pub fragment: Option<String>,
+}
+
+impl Href {
+    /// Resolve `href` as found in the document at `doc_path`. The fragment is split off before
+    /// decoding, so `%23` stays part of the path.
+    pub(crate) fn resolve(doc_path: &str, href: &str) -> Href {
+        let (path, fragment) = match href.trim().split_once('#') {
+            Some((path, fragment)) => (path, Some(fragment)),
+            None => (href, None),
+        };
+
+        Href {
+            path: path::resolve(doc_path, path),
+            fragment: fragment
+                .filter(|fragment| !fragment.is_empty())
+                .map(|fragment| fragment.to_string()),
+        }
+    }
+}
+
+impl Display for Href {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.fragment {
+            Some(fragment) => write!(f, "{}#{}", self.path, fragment),
+            None => write!(f, "{}", self.path),
+        }
+    }
+}
+
+impl TocNode {
+    /// Index of the spine item this node points to. Nodes without a link, e.g. a heading
+    /// grouping several chapters, use their first child's target.
+    pub fn spine_index(&self, spine: &Spine) -> Option<usize> {
+        match &self.href {
+            Some(href) => spine.position(&href.path),
+            None => self
+                .children
+                .as_ref()?
+                .iter()
+                .find_map(|child| child.spine_index(spine)),
+        }
+    }
 }
 
 impl Toc {
-    pub(crate) fn from_nav_doc(doc: &str, base_path: &str) -> Result<Toc> {
-        Ok(Toc {
-            contents: nav_doc::parse(doc, base_path)?,
-        })
+    pub(crate) fn from_nav_doc(doc: &str, path: &str) -> Result<Toc> {
+        nav_doc::parse(doc, path)
     }
 
-    pub(crate) fn from_ncx(doc: &str, _base_path: &str) -> Result<Toc> {
+    /// Only the landmarks and page list of a navigation document, which don't need its `toc`
+    /// nav, e.g. to complete a table of contents read from the NCX.
+    pub(crate) fn from_nav_doc_lists(doc: &str, path: &str) -> Result<Toc> {
+        let doc = xml_util::parse(doc, path)?;
+        let (landmarks, page_list) = nav_doc::lists(&doc, path);
+
         Ok(Toc {
-            contents: ncx::parse(doc, _base_path)?,
+            landmarks,
+            page_list,
+            ..Default::default()
         })
     }
+
+    pub(crate) fn from_ncx(doc: &str, path: &str) -> Result<Toc> {
+        ncx::parse(doc, path)
+    }
+
+    /// Build a flat table of contents from `(path, XHTML document)` spine pages.
+    pub(crate) fn from_spine_pages(pages: &[(String, String)]) -> Toc {
+        Toc {
+            contents: pages
+                .iter()
+                .filter_map(|(path, doc)| spine_pages::parse_page(doc, path))
+                .collect(),
+            synthesized: true,
+            ..Default::default()
+        }
+    }
 }
 
 mod nav_doc {
     use super::*;
 
-    pub(crate) fn parse(doc: &str, base_path: &str) -> Result<Vec<TocNode>> {
-        let doc = roxmltree::Document::parse(doc)?;
+    pub(crate) fn parse(doc: &str, path: &str) -> Result<Toc> {
+        let doc = xml_util::parse(doc, path)?;
         let toc_node = doc
             .descendants()
-            .find(|node| node.has_tag_name("nav") && node.attribute("id") == Some("toc"))
-            .ok_or(anyhow!("`nav(id=toc)` node not found"))?;
+            .find(|node| node.has_tag_name("nav") && has_epub_type(node, "toc"))
+            .or_else(|| {
+                doc.descendants()
+                    .find(|node| node.has_tag_name("nav") && node.attribute("id") == Some("toc"))
+            })
+            .ok_or_else(|| Error::missing_element(path, "nav(epub:type=toc)"))?;
         let ol_elem = toc_node
             .children()
             .find(|node| node.has_tag_name("ol"))
-            .ok_or(anyhow!("top level `ol` node not found"))?;
+            .ok_or_else(|| Error::missing_element(path, "ol"))?;
 
-        Ok(ol_elem
+        let contents = ol_elem
             .children()
             .filter(|node| node.has_tag_name("li"))
-            .map(|node| parse_li(node, base_path))
-            .collect())
+            .map(|node| parse_li(node, path))
+            .collect();
+        let (landmarks, page_list) = lists(&doc, path);
+
+        Ok(Toc {
+            contents,
+            landmarks,
+            page_list,
+            ..Default::default()
+        })
+    }
+
+    /// landmarks and page list
+    pub(super) fn lists(doc: &roxmltree::Document, path: &str) -> (Vec<Landmark>, Vec<PageTarget>) {
+        let landmarks = links(doc, "landmarks")
+            .filter_map(|a_elem| {
+                Some(Landmark {
+                    epub_type: a_elem
+                        .attribute((NAMESPACE_OPS, "type"))?
+                        .trim()
+                        .to_string(),
+                    title: text_norm(&a_elem),
+                    href: Href::resolve(path, a_elem.attribute("href")?),
+                })
+            })
+            .collect();
+
+        let page_list = links(doc, "page-list")
+            .filter_map(|a_elem| {
+                Some(PageTarget {
+                    label: text_norm(&a_elem),
+                    href: Href::resolve(path, a_elem.attribute("href")?),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        (landmarks, page_list)
+    }
+
+    /// `a` elements of the `nav` with the given `epub:type`, in document order
+    fn links<'a, 'input>(
+        doc: &'a roxmltree::Document<'input>,
+        epub_type: &'a str,
+    ) -> impl Iterator<Item = Node<'a, 'input>> {
+        doc.descendants()
+            .find(|node| node.has_tag_name("nav") && has_epub_type(node, epub_type))
+            .into_iter()
+            .flat_map(|nav_elem| nav_elem.descendants())
+            .filter(|node| node.has_tag_name("a"))
     }
 
-    fn parse_li(li_elem: Node, base_path: &str) -> TocNode {
+    fn has_epub_type(node: &Node, epub_type: &str) -> bool {
+        node.attribute((NAMESPACE_OPS, "type"))
+            .is_some_and(|types| types.split_ascii_whitespace().any(|t| t == epub_type))
+    }
+
+    fn parse_li(li_elem: Node, doc_path: &str) -> TocNode {
         let a_elem = li_elem.children().find(|node| node.has_tag_name("a"));
         let (title, href) = if let Some(a_elem) = a_elem {
             (
                 text_norm(&a_elem),
                 a_elem
                     .attribute("href")
-                    .map(|str| format!("{}/{}", base_path, str)),
+                    .map(|href| Href::resolve(doc_path, href)),
             )
         } else {
             li_elem
@@ -77,7 +276,7 @@ mod nav_doc {
                 ol_elem
                     .children()
                     .filter(|node| node.has_tag_name("li"))
-                    .map(|node| parse_li(node, base_path))
+                    .map(|node| parse_li(node, doc_path))
                     .collect()
             });
 
@@ -85,10 +284,12 @@ mod nav_doc {
             title,
             href,
             children,
+            id: li_elem.attribute("id").map(|str| str.to_string()),
+            ..Default::default()
         }
     }
 
-    fn collect_text(node: &Node) -> String {
+    pub(super) fn collect_text(node: &Node) -> String {
         if node.is_text() {
             node.text().unwrap().to_string()
         } else {
@@ -100,7 +301,7 @@ mod nav_doc {
         }
     }
 
-    fn text_norm(node: &Node) -> String {
+    pub(super) fn text_norm(node: &Node) -> String {
         Regex::new(r"\s+")
             .unwrap()
             .replace_all(collect_text(node).trim(), " ")
@@ -110,6 +311,7 @@ mod nav_doc {
     #[cfg(test)]
     mod tests {
         use super::*;
+        use crate::parser::toc::tests::href;
 
         #[test]
         fn normalize_html_text() {
@@ -127,7 +329,7 @@ mod nav_doc {
     <title>Table of Contents</title>
 </head>
 <body>
-<nav id="toc">
+<nav id="contents" epub:type="toc">
     <h2>Table of Contents</h2>
     <ol>
         <li><a href="preface.xhtml">Preface</a></li>
@@ -143,6 +345,18 @@ mod nav_doc {
         </li>
     </ol>
 </nav>
+<nav epub:type="landmarks" hidden="">
+    <ol>
+        <li><a epub:type="toc" href="toc.xhtml">Table of Contents</a></li>
+        <li><a epub:type="bodymatter" href="chapter-1.xhtml">Start of Content</a></li>
+    </ol>
+</nav>
+<nav epub:type="page-list" hidden="">
+    <ol>
+        <li><a href="preface.xhtml#page-iv">iv</a></li>
+        <li><a href="chapter-1.xhtml#page-1">1</a></li>
+    </ol>
+</nav>
 </body>
 </html>"#.trim();
 
@@ -151,45 +365,82 @@ mod nav_doc {
             let expected = vec![
                 TocNode {
                     title: String::from("Preface"),
-                    href: Some(format!("{}/{}", base_path, "preface.xhtml")),
+                    href: Some(href(base_path, "preface.xhtml", None)),
                     children: None,
+                    ..Default::default()
                 },
                 TocNode {
                     title: String::from("Jane Eyre"),
-                    href: Some(format!("{}/{}", base_path, "title-page.xhtml")),
+                    href: Some(href(base_path, "title-page.xhtml", None)),
                     children: Some(vec![
                         TocNode {
                             title: String::from("Chapter 1"),
-                            href: Some(format!("{}/{}", base_path, "chapter-1.xhtml")),
+                            href: Some(href(base_path, "chapter-1.xhtml", None)),
                             children: None,
+                            ..Default::default()
                         },
                         TocNode {
                             title: String::from("Chapter 2"),
-                            href: Some(format!("{}/{}", base_path, "chapter-2.xhtml")),
+                            href: Some(href(base_path, "chapter-2.xhtml", None)),
                             children: None,
+                            ..Default::default()
                         },
                         TocNode {
                             title: String::from("Chapter 3"),
-                            href: Some(format!("{}/{}", base_path, "chapter-3.xhtml")),
+                            href: Some(href(base_path, "chapter-3.xhtml", None)),
                             children: None,
+                            ..Default::default()
                         },
                         TocNode {
                             title: String::from("Chapter 4"),
-                            href: Some(format!("{}/{}", base_path, "chapter-4.xhtml")),
+                            href: Some(href(base_path, "chapter-4.xhtml", None)),
                             children: None,
+                            ..Default::default()
                         },
                         TocNode {
                             title: String::from("Chapter 5"),
                             href: None,
                             children: None,
+                            ..Default::default()
                         },
                     ]),
+                    ..Default::default()
                 },
             ];
 
-            let parsed = parse(doc, base_path).unwrap();
-
-            assert_eq!(expected, parsed);
+            let parsed = parse(doc, "epub/toc.xhtml").unwrap();
+
+            assert_eq!(expected, parsed.contents);
+            assert_eq!(
+                vec![
+                    Landmark {
+                        epub_type: String::from("toc"),
+                        title: String::from("Table of Contents"),
+                        href: href(base_path, "toc.xhtml", None),
+                    },
+                    Landmark {
+                        epub_type: String::from("bodymatter"),
+                        title: String::from("Start of Content"),
+                        href: href(base_path, "chapter-1.xhtml", None),
+                    },
+                ],
+                parsed.landmarks
+            );
+            assert_eq!(
+                vec![
+                    PageTarget {
+                        label: String::from("iv"),
+                        href: href(base_path, "preface.xhtml", Some("page-iv")),
+                        ..Default::default()
+                    },
+                    PageTarget {
+                        label: String::from("1"),
+                        href: href(base_path, "chapter-1.xhtml", Some("page-1")),
+                        ..Default::default()
+                    },
+                ],
+                parsed.page_list
+            );
         }
     }
 }
@@ -197,53 +448,89 @@ mod nav_doc {
 mod ncx {
     use super::*;
 
-    pub fn parse(doc: &str, base_path: &str) -> Result<Vec<TocNode>> {
-        let doc = roxmltree::Document::parse(doc)?;
-        let nav_map_elem = doc
-            .descendants()
+    pub fn parse(doc: &str, path: &str) -> Result<Toc> {
+        let doc = xml_util::parse(doc, path)?;
+        let ncx_elem = doc.root_element();
+        let nav_map_elem = ncx_elem
+            .children()
             .find(|node| node.has_tag_name("navMap"))
-            .ok_or(anyhow!("`navMap` node not found"))?;
+            .ok_or_else(|| Error::missing_element(path, "navMap"))?;
+
+        let contents = parse_nav_points(&nav_map_elem, "navPoint", path);
 
-        let toc_nodes = nav_map_elem
+        let mut page_list: Vec<PageTarget> = ncx_elem
             .children()
-            .filter(|node| node.has_tag_name("navPoint"))
-            .map(|node| parse_nav_point(&node, base_path))
+            .filter(|node| node.has_tag_name("pageList"))
+            .flat_map(|page_list_elem| page_list_elem.children())
+            .filter(|node| node.has_tag_name("pageTarget"))
+            .filter_map(|node| {
+                Some(PageTarget {
+                    label: label(&node),
+                    href: content_href(&node, path)?,
+                    id: attribute(&node, "id"),
+                    page_type: attribute(&node, "type"),
+                    value: node
+                        .attribute("value")
+                        .and_then(|str| str.trim().parse().ok()),
+                    play_order: play_order(&node),
+                })
+            })
             .collect();
+        if page_list.iter().all(|target| target.play_order.is_some()) {
+            page_list.sort_by_key(|target| target.play_order);
+        }
 
-        Ok(toc_nodes)
-    }
-
-    fn parse_nav_point(nav_point_elem: &Node, base_path: &str) -> TocNode {
-        let title = nav_point_elem
+        let nav_lists = ncx_elem
             .children()
-            .find(|node| node.has_tag_name("navLabel"))
-            .map(|nav_label_elem| {
-                nav_label_elem
-                    .children()
-                    .find(|node| node.has_tag_name("text"))
-                    .map(|text_elem| text_elem.text())
-                    .flatten()
+            .filter(|node| node.has_tag_name("navList"))
+            .map(|nav_list_elem| NavList {
+                id: attribute(&nav_list_elem, "id"),
+                class: attribute(&nav_list_elem, "class"),
+                title: label(&nav_list_elem),
+                targets: parse_nav_points(&nav_list_elem, "navTarget", path),
             })
-            .flatten()
-            .unwrap_or_default()
-            .to_string();
+            .collect();
 
-        let href = nav_point_elem
+        let doc_title = ncx_elem
             .children()
-            .find(|node| node.has_tag_name("content"))
-            .map(|content_elem| {
-                content_elem
-                    .attribute("src")
-                    .map(|str| format!("{}/{}", base_path, str))
-            })
-            .flatten();
+            .find(|node| node.has_tag_name("docTitle"))
+            .map(|node| text(&node))
+            .filter(|title| !title.is_empty());
+
+        let doc_authors = ncx_elem
+            .children()
+            .filter(|node| node.has_tag_name("docAuthor"))
+            .map(|node| text(&node))
+            .filter(|author| !author.is_empty())
+            .collect();
+
+        Ok(Toc {
+            contents,
+            page_list,
+            nav_lists,
+            doc_title,
+            doc_authors,
+            ..Default::default()
+        })
+    }
 
-        let children: Vec<TocNode> = nav_point_elem
+    /// Parse `navPoint` or `navTarget` children, ordered by `playOrder` when every sibling
+    /// declares one.
+    fn parse_nav_points(parent_elem: &Node, tag_name: &str, doc_path: &str) -> Vec<TocNode> {
+        let mut nodes: Vec<TocNode> = parent_elem
             .children()
-            .filter(|node| node.has_tag_name("navPoint"))
-            .map(|node| parse_nav_point(&node, base_path))
+            .filter(|node| node.has_tag_name(tag_name))
+            .map(|node| parse_nav_point(&node, doc_path))
             .collect();
+        if nodes.iter().all(|node| node.play_order.is_some()) {
+            nodes.sort_by_key(|node| node.play_order);
+        }
+
+        nodes
+    }
 
+    fn parse_nav_point(nav_point_elem: &Node, doc_path: &str) -> TocNode {
+        let children = parse_nav_points(nav_point_elem, "navPoint", doc_path);
         let children = if children.is_empty() {
             None
         } else {
@@ -251,65 +538,282 @@ mod ncx {
         };
 
         TocNode {
-            title,
-            href,
+            title: label(nav_point_elem),
+            href: content_href(nav_point_elem, doc_path),
             children,
+            id: attribute(nav_point_elem, "id"),
+            class: attribute(nav_point_elem, "class"),
+            play_order: play_order(nav_point_elem),
         }
     }
 
+    /// text of the first `navLabel`
+    fn label(elem: &Node) -> String {
+        elem.children()
+            .find(|node| node.has_tag_name("navLabel"))
+            .map(|nav_label_elem| text(&nav_label_elem))
+            .unwrap_or_default()
+    }
+
+    /// text of the `text` child, e.g. of `navLabel` or `docTitle`
+    fn text(elem: &Node) -> String {
+        elem.children()
+            .find(|node| node.has_tag_name("text"))
+            .and_then(|text_elem| text_elem.text())
+            .unwrap_or_default()
+            .trim()
+            .to_string()
+    }
+
+    fn content_href(elem: &Node, doc_path: &str) -> Option<Href> {
+        elem.children()
+            .find(|node| node.has_tag_name("content"))
+            .and_then(|content_elem| content_elem.attribute("src"))
+            .map(|src| Href::resolve(doc_path, src))
+    }
+
+    fn attribute(elem: &Node, name: &str) -> Option<String> {
+        elem.attribute(name).map(|str| str.to_string())
+    }
+
+    fn play_order(elem: &Node) -> Option<u32> {
+        elem.attribute("playOrder")
+            .and_then(|str| str.trim().parse().ok())
+    }
+
     #[cfg(test)]
     mod tests {
         use super::*;
+        use crate::parser::toc::tests::href;
 
         #[test]
         fn parse_ncx() {
             let doc = r#"
 <ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1" xml:lang="en-US">
+    <docTitle>
+        <text>Jane Eyre</text>
+    </docTitle>
+    <docAuthor>
+        <text>Charlotte Brontë</text>
+    </docAuthor>
     <navMap>
-        <navPoint class="h1" id="ch1">
+        <navPoint class="h1" id="ncx-2" playOrder="3">
+            <navLabel>
+                <text>Chapter 2</text>
+            </navLabel>
+            <content src="content.html#ch_2"/>
+        </navPoint>
+        <navPoint class="h1" id="ch1" playOrder="1">
             <navLabel>
                 <text>Chapter 1</text>
             </navLabel>
             <content src="content.html#ch_1"/>
-            <navPoint class="h2" id="ch_1_1">
+            <navPoint class="h2" id="ch_1_1" playOrder="2">
                 <navLabel>
                     <text>Chapter 1.1</text>
                 </navLabel>
                 <content src="content.html#ch_1_1"/>
             </navPoint>
         </navPoint>
-        <navPoint class="h1" id="ncx-2">
-            <navLabel>
-                <text>Chapter 2</text>
-            </navLabel>
-            <content src="content.html#ch_2"/>
-        </navPoint>
     </navMap>
+    <pageList>
+        <pageTarget id="page-1" type="normal" value="1" playOrder="4">
+            <navLabel><text>1</text></navLabel>
+            <content src="content.html#page_1"/>
+        </pageTarget>
+    </pageList>
+    <navList class="lot">
+        <navLabel><text>List of Tables</text></navLabel>
+        <navTarget id="table-1">
+            <navLabel><text>Table 1</text></navLabel>
+            <content src="content.html#table_1"/>
+        </navTarget>
+    </navList>
 </ncx>"#
                 .trim();
 
             let base_path = "epub";
 
-            let expected = vec![
-                TocNode {
-                    title: String::from("Chapter 1"),
-                    href: Some(format!("{}/{}", base_path, "content.html#ch_1")),
-                    children: Some(vec![TocNode {
-                        title: String::from("Chapter 1.1"),
-                        href: Some(format!("{}/{}", base_path, "content.html#ch_1_1")),
+            let expected = Toc {
+                contents: vec![
+                    TocNode {
+                        title: String::from("Chapter 1"),
+                        href: Some(href(base_path, "content.html", Some("ch_1"))),
+                        children: Some(vec![TocNode {
+                            title: String::from("Chapter 1.1"),
+                            href: Some(href(base_path, "content.html", Some("ch_1_1"))),
+                            children: None,
+                            id: Some(String::from("ch_1_1")),
+                            class: Some(String::from("h2")),
+                            play_order: Some(2),
+                        }]),
+                        id: Some(String::from("ch1")),
+                        class: Some(String::from("h1")),
+                        play_order: Some(1),
+                    },
+                    TocNode {
+                        title: String::from("Chapter 2"),
+                        href: Some(href(base_path, "content.html", Some("ch_2"))),
                         children: None,
-                    }]),
-                },
-                TocNode {
-                    title: String::from("Chapter 2"),
-                    href: Some(format!("{}/{}", base_path, "content.html#ch_2")),
-                    children: None,
-                },
+                        id: Some(String::from("ncx-2")),
+                        class: Some(String::from("h1")),
+                        play_order: Some(3),
+                    },
+                ],
+                page_list: vec![PageTarget {
+                    label: String::from("1"),
+                    href: href(base_path, "content.html", Some("page_1")),
+                    id: Some(String::from("page-1")),
+                    page_type: Some(String::from("normal")),
+                    value: Some(1),
+                    play_order: Some(4),
+                }],
+                nav_lists: vec![NavList {
+                    id: None,
+                    class: Some(String::from("lot")),
+                    title: String::from("List of Tables"),
+                    targets: vec![TocNode {
+                        title: String::from("Table 1"),
+                        href: Some(href(base_path, "content.html", Some("table_1"))),
+                        id: Some(String::from("table-1")),
+                        ..Default::default()
+                    }],
+                }],
+                doc_title: Some(String::from("Jane Eyre")),
+                doc_authors: vec![String::from("Charlotte Brontë")],
+                ..Default::default()
+            };
+
+            let parsed = parse(doc, "epub/toc.ncx").unwrap();
+
+            assert_eq!(expected, parsed)
+        }
+    }
+}
+
+mod spine_pages {
+    use super::*;
+
+    /// Title a page by its first heading, falling back to `<title>`, which often just repeats
+    /// the book title. Pages that are not well-formed or have neither are skipped.
+    pub(super) fn parse_page(doc: &str, path: &str) -> Option<TocNode> {
+        let doc = xml_util::replace_html_entities(doc);
+        let doc = roxmltree::Document::parse(&doc).ok()?;
+        let heading = doc
+            .descendants()
+            .find(|node| {
+                matches!(
+                    node.tag_name().name(),
+                    "h1" | "h2" | "h3" | "h4" | "h5" | "h6"
+                )
+            })
+            .map(|node| nav_doc::text_norm(&node))
+            .filter(|title| !title.is_empty());
+        let title = heading.or_else(|| {
+            doc.descendants()
+                .find(|node| node.has_tag_name("title"))
+                .map(|node| nav_doc::text_norm(&node))
+                .filter(|title| !title.is_empty())
+        })?;
+
+        Some(TocNode {
+            title,
+            href: Some(Href {
+                path: path.to_string(),
+                fragment: None,
+            }),
+            ..Default::default()
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn synthesize_toc() {
+            let pages = [
+                (
+                    String::from("epub/title.xhtml"),
+                    String::from(
+                        "<html><head><title>Jane Eyre</title></head><body><p>by Charlotte Brontë</p></body></html>",
+                    ),
+                ),
+                (
+                    String::from("epub/chapter-1.xhtml"),
+                    String::from(
+                        "<html><head><title>Jane Eyre</title></head><body><h2> Chapter <em>I</em></h2></body></html>",
+                    ),
+                ),
+                (
+                    String::from("epub/chapter-2.xhtml"),
+                    String::from("<html><body><h2>Chapter&nbsp;II</h2></body></html>"),
+                ),
+                (
+                    String::from("epub/image.xhtml"),
+                    String::from("<html><body><img src=\"a.png\"/></body></html>"),
+                ),
             ];
 
-            let parsed = parse(doc, base_path).unwrap();
+            let toc = Toc::from_spine_pages(&pages);
+
+            assert!(toc.synthesized);
+            assert_eq!(
+                vec!["Jane Eyre", "Chapter I", "Chapter II"],
+                toc.contents
+                    .iter()
+                    .map(|node| node.title.as_str())
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(
+                "epub/chapter-1.xhtml",
+                toc.contents[1].href.as_ref().unwrap().path
+            );
+        }
+    }
+}
 
-            assert_eq!(expected, parsed)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::spine::SpineItem;
+
+    pub(crate) fn href(base_path: &str, path: &str, fragment: Option<&str>) -> Href {
+        Href {
+            path: format!("{}/{}", base_path, path),
+            fragment: fragment.map(|str| str.to_string()),
         }
     }
+
+    #[test]
+    fn map_toc_node_to_spine_index() {
+        let spine = Spine {
+            items: ["epub/preface.xhtml", "epub/content.html"]
+                .iter()
+                .map(|path| SpineItem {
+                    path: path.to_string(),
+                    linear: true,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let node = TocNode {
+            title: String::from("Part 1"),
+            href: None,
+            children: Some(vec![TocNode {
+                title: String::from("Chapter 1"),
+                href: Some(Href::resolve("epub/toc.ncx", "content.html#ch_1")),
+                children: None,
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+
+        assert_eq!(Some(1), node.spine_index(&spine));
+        assert_eq!(
+            "epub/content.html#ch_1",
+            node.children.unwrap()[0].href.as_ref().unwrap().to_string()
+        );
+    }
 }
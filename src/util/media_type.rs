@@ -0,0 +1,30 @@
+/// Media type of a resource from its file extension, for the EPUB core media types and the
+/// resources commonly found next to them.
+pub fn guess(path: &str) -> Option<String> {
+    let extension = path.rsplit_once('.')?.1.to_ascii_lowercase();
+    let media_type = match extension.as_str() {
+        "xhtml" | "xht" | "html" | "htm" => "application/xhtml+xml",
+        "css" => "text/css",
+        "js" => "application/javascript",
+        "ncx" => "application/x-dtbncx+xml",
+        "opf" => "application/oebps-package+xml",
+        "smil" => "application/smil+xml",
+        "pls" => "application/pls+xml",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "otf" => "font/otf",
+        "ttf" => "font/ttf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "mp3" => "audio/mpeg",
+        "m4a" | "mp4" => "audio/mp4",
+        "opus" => "audio/opus",
+        "vtt" => "text/vtt",
+        _ => return None,
+    };
+
+    Some(media_type.to_string())
+}
@@ -1 +1,7 @@
-pub mod zip_util;
\ No newline at end of file
+pub mod html_entity;
+pub mod media_type;
+pub mod path;
+#[cfg(test)]
+pub(crate) mod test_util;
+pub mod xml_util;
+pub mod zip_util;
@@ -0,0 +1,252 @@
+/// Resolve `href` as found in the document at `doc_path` to a path in the archive.
+///
+/// Handles `./` and `../` segments, percent-encoding and documents at the archive root.
+/// The fragment, if any, is kept. Remote hrefs such as `https://...` are returned unchanged.
+pub fn resolve(doc_path: &str, href: &str) -> String {
+    let href = href.trim();
+    if is_remote(href) {
+        return href.to_string();
+    }
+
+    let (path, fragment) = match href.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (href, None),
+    };
+    // a query never addresses a different resource inside the archive
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+
+    let mut segments: Vec<String> = if path.starts_with('/') {
+        vec![]
+    } else {
+        parent(doc_path)
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| segment.to_string())
+            .collect()
+    };
+
+    if path.is_empty() {
+        // a same-document reference such as `#note-1`
+        segments.push(file_name(doc_path).to_string());
+    }
+
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(percent_decode(segment)),
+        }
+    }
+
+    let mut resolved = segments.join("/");
+    if let Some(fragment) = fragment {
+        resolved.push('#');
+        resolved.push_str(fragment);
+    }
+
+    resolved
+}
+
+/// Href reaching the archive path `path` from the document at `doc_path`, the inverse of
+/// [`resolve`]. Characters not allowed in a relative URL are percent-encoded.
+pub fn relative(doc_path: &str, path: &str) -> String {
+    let (path, fragment) = match path.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (path, None),
+    };
+
+    let base: Vec<&str> = parent(doc_path)
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    let target: Vec<&str> = path.split('/').collect();
+    // the last target segment is the file name, never shared with the base directory
+    let common = base
+        .iter()
+        .zip(&target[..target.len() - 1])
+        .take_while(|(base, target)| base == target)
+        .count();
+
+    let mut segments: Vec<String> = vec!["..".to_string(); base.len() - common];
+    segments.extend(
+        target[common..]
+            .iter()
+            .map(|segment| percent_encode(segment)),
+    );
+
+    let mut relative = segments.join("/");
+    if let Some(fragment) = fragment {
+        relative.push('#');
+        relative.push_str(fragment);
+    }
+
+    relative
+}
+
+/// directory of `path`, `""` for entries at the archive root
+pub fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit_once('/').map_or(path, |(_, name)| name)
+}
+
+/// whether `href` has a URL scheme, e.g. `https:`, `mailto:` or `data:`
+pub fn is_remote(href: &str) -> bool {
+    match href.split_once(':') {
+        Some((scheme, _)) => {
+            !scheme.is_empty()
+                && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+                && !href[..scheme.len()].contains('/')
+        }
+        None => false,
+    }
+}
+
+/// Decode `%XX` escapes as UTF-8, leaving malformed escapes untouched.
+pub fn percent_decode(str: &str) -> String {
+    let bytes = str.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && i + 2 < bytes.len()
+            && bytes[i + 1].is_ascii_hexdigit()
+            && bytes[i + 2].is_ascii_hexdigit()
+        {
+            decoded.push(hex_value(bytes[i + 1]) << 4 | hex_value(bytes[i + 2]));
+            i += 3;
+            continue;
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8(decoded).unwrap_or_else(|_| str.to_string())
+}
+
+/// Encode the ASCII characters not allowed in a path segment, keeping other Unicode as is.
+fn percent_encode(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for c in segment.chars() {
+        if !c.is_ascii() || c.is_ascii_alphanumeric() || "-._~!$&'()*+,;=:@".contains(c) {
+            encoded.push(c);
+        } else {
+            encoded.push_str(&format!("%{:02X}", c as u32));
+        }
+    }
+
+    encoded
+}
+
+fn hex_value(digit: u8) -> u8 {
+    match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        _ => digit - b'A' + 10,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_hrefs() {
+        let cases = [
+            // package document at the archive root
+            (
+                "content.opf",
+                "text/chapter-1.xhtml",
+                "text/chapter-1.xhtml",
+            ),
+            (
+                "OEBPS/content.opf",
+                "text/chapter-1.xhtml",
+                "OEBPS/text/chapter-1.xhtml",
+            ),
+            (
+                "OEBPS/content.opf",
+                "./images/cover.jpg",
+                "OEBPS/images/cover.jpg",
+            ),
+            // nav document in a sub directory
+            (
+                "OEBPS/nav/toc.xhtml",
+                "../text/chapter-1.xhtml#ch_1",
+                "OEBPS/text/chapter-1.xhtml#ch_1",
+            ),
+            (
+                "OEBPS/text/notes.xhtml",
+                "#note-1",
+                "OEBPS/text/notes.xhtml#note-1",
+            ),
+            (
+                "OEBPS/content.opf",
+                "text/Chapter%201.xhtml",
+                "OEBPS/text/Chapter 1.xhtml",
+            ),
+            (
+                "OEBPS/content.opf",
+                "text/%E7%AB%A0.xhtml",
+                "OEBPS/text/章.xhtml",
+            ),
+            (
+                "OEBPS/content.opf",
+                "text/100%.xhtml",
+                "OEBPS/text/100%.xhtml",
+            ),
+            ("OEBPS/content.opf", "/images/cover.jpg", "images/cover.jpg"),
+            ("content.opf", "../../cover.jpg", "cover.jpg"),
+            (
+                "OEBPS/content.opf",
+                "https://example.com/a.css",
+                "https://example.com/a.css",
+            ),
+        ];
+
+        for (doc_path, href, expected) in cases {
+            assert_eq!(expected, resolve(doc_path, href), "{} + {}", doc_path, href);
+        }
+
+        let cases = [
+            (
+                "content.opf",
+                "text/chapter-1.xhtml",
+                "text/chapter-1.xhtml",
+            ),
+            (
+                "EPUB/content.opf",
+                "EPUB/text/Chapter 1.xhtml",
+                "text/Chapter%201.xhtml",
+            ),
+            (
+                "EPUB/nav/toc.xhtml",
+                "EPUB/text/章.xhtml#ch_1",
+                "../text/章.xhtml#ch_1",
+            ),
+            (
+                "EPUB/text/notes.xhtml",
+                "EPUB/text/notes.xhtml#n1",
+                "notes.xhtml#n1",
+            ),
+            ("EPUB/text/notes.xhtml", "cover.jpg", "../../cover.jpg"),
+        ];
+        for (doc_path, path, expected) in cases {
+            assert_eq!(
+                expected,
+                relative(doc_path, path),
+                "{} -> {}",
+                doc_path,
+                path
+            );
+            assert_eq!(path, resolve(doc_path, expected));
+        }
+    }
+}
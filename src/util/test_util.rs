@@ -0,0 +1,94 @@
+use std::io::{Cursor, Write};
+
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Build an in-memory zip archive from `(path, content)` pairs, in the given order.
+pub fn build_zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for (path, content) in entries {
+        let options = if *path == "mimetype" {
+            FileOptions::default().compression_method(CompressionMethod::Stored)
+        } else {
+            FileOptions::default()
+        };
+        writer.start_file(*path, options).unwrap();
+        writer.write_all(content).unwrap();
+    }
+
+    writer.finish().unwrap().into_inner()
+}
+
+pub const CONTAINER: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<container xmlns="urn:oasis:names:tc:opendocument:xmlns:container" version="1.0">
+    <rootfiles>
+        <rootfile full-path="epub/content.opf" media-type="application/oebps-package+xml"/>
+    </rootfiles>
+</container>"#;
+
+pub const PACKAGE_DOCUMENT: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" unique-identifier="uid" version="3.0">
+    <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+        <dc:identifier id="uid">urn:uuid:d3a1e1f2-8a55-4b5b-9f0e-3c7e9d3c2b10</dc:identifier>
+        <dc:title>Sample Book</dc:title>
+        <dc:language>en</dc:language>
+    </metadata>
+    <manifest>
+        <item href="chapter-1.xhtml" id="chapter-1" media-type="application/xhtml+xml"/>
+        <item href="toc.xhtml" id="toc" media-type="application/xhtml+xml" properties="nav"/>
+    </manifest>
+    <spine>
+        <itemref idref="chapter-1"/>
+    </spine>
+</package>"#;
+
+pub const NAV_DOC: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head><title>Table of Contents</title></head>
+<body>
+<nav id="toc" epub:type="toc">
+    <ol>
+        <li><a href="chapter-1.xhtml">Chapter 1</a></li>
+    </ol>
+</nav>
+</body>
+</html>"#;
+
+pub const CHAPTER: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head><title>Chapter 1</title></head>
+<body>
+<h1>Chapter 1</h1>
+<p>It was a dark and stormy night.</p>
+</body>
+</html>"#;
+
+/// Build a book: `mimetype`, `container.xml` pointing at `epub/content.opf`, `pkg_doc` there and
+/// then the other `(path, content)` entries.
+pub fn epub(pkg_doc: &str, entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut all: Vec<(&str, &[u8])> = vec![
+        ("mimetype", b"application/epub+zip"),
+        ("META-INF/container.xml", CONTAINER.as_bytes()),
+        ("epub/content.opf", pkg_doc.as_bytes()),
+    ];
+    all.extend_from_slice(entries);
+
+    build_zip(&all)
+}
+
+/// `pkg_doc` with `items`, e.g. `<item href="toc.ncx" id="ncx" .../>`, added to the manifest.
+pub fn with_items(pkg_doc: &str, items: &[&str]) -> String {
+    let items: String = items.iter().map(|item| format!("  {}\n  ", item)).collect();
+    pkg_doc.replacen("</manifest>", &format!("{}</manifest>", items), 1)
+}
+
+/// A minimal, valid EPUB 3 book.
+pub fn sample_epub() -> Vec<u8> {
+    epub(
+        PACKAGE_DOCUMENT,
+        &[
+            ("epub/toc.xhtml", NAV_DOC.as_bytes()),
+            ("epub/chapter-1.xhtml", CHAPTER.as_bytes()),
+        ],
+    )
+}
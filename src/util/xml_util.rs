@@ -0,0 +1,47 @@
+use std::borrow::Cow;
+
+use regex::{Captures, Regex};
+use roxmltree::Document;
+
+use crate::error::{Error, Result};
+use crate::util::html_entity;
+
+/// Parse an XML document read from `path` in the archive.
+pub fn parse<'a>(doc: &'a str, path: &str) -> Result<Document<'a>> {
+    Document::parse(doc).map_err(|err| Error::Xml {
+        path: path.to_string(),
+        source: err,
+    })
+}
+
+/// Escape `str` for use as XML text or a double quoted attribute value.
+pub fn escape(str: &str) -> String {
+    let mut escaped = String::with_capacity(str.len());
+    for c in str.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Replace the HTML named character references XML doesn't predefine, e.g. `&nbsp;`, with
+/// numeric ones so XHTML relying on the XHTML DTD parses.
+pub fn replace_html_entities(doc: &str) -> Cow<'_, str> {
+    Regex::new(r"&([A-Za-z][A-Za-z0-9]*);")
+        .unwrap()
+        .replace_all(doc, |caps: &Captures| {
+            let name = &caps[1];
+            match html_entity::code_point(name) {
+                Some(code_point) if !["amp", "lt", "gt", "quot", "apos"].contains(&name) => {
+                    format!("&#x{:X};", code_point)
+                }
+                _ => caps[0].to_string(),
+            }
+        })
+}
@@ -1,21 +1,46 @@
-use std::fs::File;
 use std::io;
-use std::io::Read;
+use std::io::{Read, Seek};
 
-use anyhow::Result;
+use zip::read::ZipFile;
+use zip::result::ZipError;
 use zip::ZipArchive;
 
-pub fn read_text_file(archive: &mut ZipArchive<File>, path: &str) -> Result<String> {
-    let file = archive.by_name(path)?;
+use crate::error::{Error, Result};
 
-    return Ok(io::read_to_string(file)?);
+pub fn read_text_file<R: Read + Seek>(archive: &mut ZipArchive<R>, path: &str) -> Result<String> {
+    let file = by_name(archive, path)?;
+
+    io::read_to_string(file).map_err(|err| resource_error(path, err))
 }
 
-pub fn read_binary_file(archive: &mut ZipArchive<File>, path: &str) -> Result<Vec<u8>> {
-    let mut file = archive.by_name(path)?;
+pub fn read_binary_file<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    path: &str,
+) -> Result<Vec<u8>> {
+    let mut file = by_name(archive, path)?;
 
     let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)?;
+    file.read_to_end(&mut buffer)
+        .map_err(|err| resource_error(path, err))?;
+
+    Ok(buffer)
+}
+
+fn by_name<'a, R: Read + Seek>(archive: &'a mut ZipArchive<R>, path: &str) -> Result<ZipFile<'a>> {
+    archive.by_name(path).map_err(|err| match err {
+        ZipError::FileNotFound => Error::MissingResource {
+            path: path.to_string(),
+        },
+        err => Error::Resource {
+            path: path.to_string(),
+            source: err,
+        },
+    })
+}
 
-    return Ok(buffer);
+fn resource_error(path: &str, err: io::Error) -> Error {
+    Error::Resource {
+        path: path.to_string(),
+        source: ZipError::Io(err),
+    }
 }
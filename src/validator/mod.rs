@@ -0,0 +1,530 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Seek};
+
+use roxmltree::Node;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use zip::{CompressionMethod, ZipArchive};
+
+use crate::error::Error;
+use crate::parser::{Container, CONTAINER_PATH};
+use crate::util::zip_util::read_text_file;
+
+mod package;
+
+const MIMETYPE_PATH: &str = "mimetype";
+const MIMETYPE: &str = "application/epub+zip";
+
+/// Findings of `Parser::validate`, in the order they were found.
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Report {
+    pub items: Vec<ReportItem>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ReportItem {
+    pub severity: Severity,
+    pub code: Code,
+    pub location: Location,
+    /// human readable description, naming the offending value
+    pub message: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Severity {
+    /// the book breaks the EPUB specification, reading systems may reject it
+    Error,
+    /// the book is valid but likely to misbehave in some reading systems
+    Warning,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Code {
+    /// no `mimetype` entry
+    MimetypeMissing,
+    /// `mimetype` is not the first entry of the archive
+    MimetypeNotFirst,
+    /// `mimetype` is compressed
+    MimetypeCompressed,
+    /// `mimetype` does not contain exactly `application/epub+zip`
+    MimetypeContent,
+    /// no `META-INF/container.xml`
+    ContainerMissing,
+    /// `container.xml` does not declare any `rootfile`
+    NoRootFile,
+    /// the package document declared in `container.xml` does not exist
+    PackageDocumentMissing,
+    /// an XML document is not well-formed
+    MalformedXml,
+    /// a required element is missing, e.g. the `spine`
+    MissingElement,
+    /// a required attribute is missing, e.g. a manifest item without `href`
+    MissingAttribute,
+    /// an archive entry could not be read, e.g. a corrupt entry
+    ResourceUnreadable,
+    /// no `dc:identifier`
+    MissingIdentifier,
+    /// `package@unique-identifier` does not match any `dc:identifier`
+    UniqueIdentifierNotFound,
+    /// no `dc:title`
+    MissingTitle,
+    /// no `dc:language`
+    MissingLanguage,
+    /// no `dcterms:modified` in an EPUB 3 package
+    MissingModified,
+    /// several manifest items share an id
+    DuplicateId,
+    /// a manifest item is not in the archive
+    ResourceMissing,
+    /// an archive entry is not declared in the manifest
+    ResourceUndeclared,
+    /// a manifest item without `media-type`
+    MissingMediaType,
+    /// a media type outside the EPUB core media types, without a fallback
+    UnknownMediaType,
+    /// an `idref`, `fallback`, `media-overlay` or `spine@toc` pointing to no manifest item
+    IdrefNotFound,
+    /// the spine has no `itemref`
+    SpineEmpty,
+    /// a spine item that is not an XHTML or SVG content document and has no fallback to one
+    NonContentSpineItem,
+    /// an EPUB 3 package without a `nav` item
+    MissingNavDocument,
+    /// a table of contents, landmark or page list link to a resource outside the manifest
+    TocHrefNotInManifest,
+}
+
+/// where a finding was made: an archive path, plus a position in XML documents
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Location {
+    pub path: String,
+    /// 1-based
+    pub line: Option<u32>,
+    /// 1-based
+    pub column: Option<u32>,
+}
+
+impl Report {
+    /// whether the book has no errors, warnings aside
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &ReportItem> {
+        self.items
+            .iter()
+            .filter(|item| item.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &ReportItem> {
+        self.items
+            .iter()
+            .filter(|item| item.severity == Severity::Warning)
+    }
+
+    pub(crate) fn push(&mut self, code: Code, location: Location, message: String) {
+        self.items.push(ReportItem {
+            severity: code.severity(),
+            code,
+            location,
+            message,
+        });
+    }
+
+    /// Report an error returned by the parser while reading the document at `path`.
+    pub(crate) fn push_error(&mut self, err: Error, path: &str) {
+        let (code, location) = match &err {
+            Error::Xml { path, source } => {
+                let pos = source.pos();
+                let location = Location {
+                    path: path.clone(),
+                    line: Some(pos.row),
+                    column: Some(pos.col),
+                };
+                (Code::MalformedXml, location)
+            }
+            Error::MissingResource { path } => (Code::ResourceMissing, Location::new(path)),
+            Error::MissingPackageDocument { path } => {
+                (Code::PackageDocumentMissing, Location::new(path))
+            }
+            Error::MissingElement { path, .. } => (Code::MissingElement, Location::new(path)),
+            Error::NoRootFile => (Code::NoRootFile, Location::new(CONTAINER_PATH)),
+            _ => (Code::ResourceUnreadable, Location::new(path)),
+        };
+        self.push(code, location, err.to_string());
+    }
+}
+
+impl Code {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Code::ResourceUndeclared | Code::UnknownMediaType => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    /// kebab-case name, e.g. `mimetype-missing`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Code::MimetypeMissing => "mimetype-missing",
+            Code::MimetypeNotFirst => "mimetype-not-first",
+            Code::MimetypeCompressed => "mimetype-compressed",
+            Code::MimetypeContent => "mimetype-content",
+            Code::ContainerMissing => "container-missing",
+            Code::NoRootFile => "no-root-file",
+            Code::PackageDocumentMissing => "package-document-missing",
+            Code::MalformedXml => "malformed-xml",
+            Code::MissingElement => "missing-element",
+            Code::MissingAttribute => "missing-attribute",
+            Code::ResourceUnreadable => "resource-unreadable",
+            Code::MissingIdentifier => "missing-identifier",
+            Code::UniqueIdentifierNotFound => "unique-identifier-not-found",
+            Code::MissingTitle => "missing-title",
+            Code::MissingLanguage => "missing-language",
+            Code::MissingModified => "missing-modified",
+            Code::DuplicateId => "duplicate-id",
+            Code::ResourceMissing => "resource-missing",
+            Code::ResourceUndeclared => "resource-undeclared",
+            Code::MissingMediaType => "missing-media-type",
+            Code::UnknownMediaType => "unknown-media-type",
+            Code::IdrefNotFound => "idref-not-found",
+            Code::SpineEmpty => "spine-empty",
+            Code::NonContentSpineItem => "non-content-spine-item",
+            Code::MissingNavDocument => "missing-nav-document",
+            Code::TocHrefNotInManifest => "toc-href-not-in-manifest",
+        }
+    }
+}
+
+impl Location {
+    pub(crate) fn new(path: &str) -> Location {
+        Location {
+            path: path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// position of `node` in the document at `path`
+    pub(crate) fn at(path: &str, node: &Node) -> Location {
+        let pos = node.document().text_pos_at(node.range().start);
+        Location {
+            path: path.to_string(),
+            line: Some(pos.row),
+            column: Some(pos.col),
+        }
+    }
+}
+
+impl Display for ReportItem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(
+            f,
+            "{}[{}] {}: {}",
+            severity,
+            self.code.as_str(),
+            self.location,
+            self.message
+        )
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.path)?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Check the container, every rendition's package document and their tables of contents.
+///
+/// Only needs a readable zip archive: documents that fail to parse are reported and the
+/// checks depending on them skipped.
+pub(crate) fn validate<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Report {
+    let mut report = Report::default();
+    check_mimetype(archive, &mut report);
+
+    let container = match read_text_file(archive, CONTAINER_PATH) {
+        Ok(container) => container,
+        Err(Error::MissingResource { .. }) => {
+            report.push(
+                Code::ContainerMissing,
+                Location::new(CONTAINER_PATH),
+                format!("`{}` not found", CONTAINER_PATH),
+            );
+            return report;
+        }
+        Err(err) => {
+            report.push_error(err, CONTAINER_PATH);
+            return report;
+        }
+    };
+    let container = match Container::from(&container) {
+        Ok(container) => container,
+        Err(err) => {
+            report.push_error(err, CONTAINER_PATH);
+            return report;
+        }
+    };
+    if container.renditions().next().is_none() {
+        report.push_error(Error::NoRootFile, CONTAINER_PATH);
+        return report;
+    }
+
+    let entries: HashSet<String> = archive.file_names().map(|str| str.to_string()).collect();
+    let mut declared = HashSet::new();
+    let mut complete = true;
+    for root_file in container.renditions() {
+        declared.insert(root_file.full_path.clone());
+        complete &= package::check(archive, &entries, root_file, &mut declared, &mut report);
+    }
+
+    // a package that failed to parse would flag all of its resources
+    if complete {
+        let mut undeclared: Vec<&String> = entries
+            .iter()
+            .filter(|entry| {
+                !entry.ends_with('/')
+                    && entry.as_str() != MIMETYPE_PATH
+                    && !entry.starts_with("META-INF/")
+                    && !declared.contains(*entry)
+            })
+            .collect();
+        undeclared.sort();
+        for entry in undeclared {
+            report.push(
+                Code::ResourceUndeclared,
+                Location::new(entry),
+                format!("`{}` is not declared in the manifest", entry),
+            );
+        }
+    }
+
+    report
+}
+
+/// `mimetype` must be the first entry, stored, and hold the EPUB media type without any
+/// trailing whitespace
+fn check_mimetype<R: Read + Seek>(archive: &mut ZipArchive<R>, report: &mut Report) {
+    let location = Location::new(MIMETYPE_PATH);
+    let Ok(mut file) = archive.by_name(MIMETYPE_PATH) else {
+        report.push(
+            Code::MimetypeMissing,
+            location,
+            format!("`{}` not found", MIMETYPE_PATH),
+        );
+        return;
+    };
+
+    if file.header_start() != 0 {
+        report.push(
+            Code::MimetypeNotFirst,
+            location.clone(),
+            format!("`{}` is not the first entry of the archive", MIMETYPE_PATH),
+        );
+    }
+    if file.compression() != CompressionMethod::Stored {
+        report.push(
+            Code::MimetypeCompressed,
+            location.clone(),
+            format!("`{}` is compressed", MIMETYPE_PATH),
+        );
+    }
+    let mut content = Vec::new();
+    if file.read_to_end(&mut content).is_err() || content != MIMETYPE.as_bytes() {
+        report.push(
+            Code::MimetypeContent,
+            location,
+            format!(
+                "`{}` does not contain exactly `{}`: `{}`",
+                MIMETYPE_PATH,
+                MIMETYPE,
+                String::from_utf8_lossy(&content)
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    use super::*;
+    use crate::builder::EpubBuilder;
+    use crate::parser::{ManifestItem, Metadata, Parser, Spine, SpineItem, Title};
+    use crate::util::test_util::{build_zip, epub, CHAPTER, CONTAINER};
+
+    #[test]
+    fn validate_broken_book() {
+        let package_document = r#"<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" unique-identifier="uid" version="3.0">
+    <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+        <dc:identifier id="uid">urn:uuid:d3a1e1f2-8a55-4b5b-9f0e-3c7e9d3c2b10</dc:identifier>
+        <dc:title>Sample Book</dc:title>
+    </metadata>
+    <manifest>
+        <item href="chapter-1.xhtml" id="chapter-1" media-type="application/xhtml+xml"/>
+        <item href="chapter-2.xhtml" id="chapter-2" media-type="application/xhtml+xml"/>
+        <item href="font.ttf" id="font" media-type="application/x-font-truetype"/>
+        <item href="toc.xhtml" id="toc" media-type="application/xhtml+xml" properties="nav"/>
+    </manifest>
+    <spine>
+        <itemref idref="chapter-1"/>
+        <itemref idref="chapter-3"/>
+    </spine>
+</package>"#;
+        let nav_doc = r#"<?xml version="1.0" encoding="utf-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head><title>Table of Contents</title></head>
+<body>
+<nav epub:type="toc">
+    <ol>
+        <li><a href="chapter-1.xhtml">Chapter 1</a></li>
+        <li><a href="appendix.xhtml#start">Appendix</a></li>
+    </ol>
+</nav>
+</body>
+</html>"#;
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (path, content) in [
+            ("META-INF/container.xml", CONTAINER),
+            ("mimetype", "application/epub+zip\n"),
+            ("epub/content.opf", package_document),
+            ("epub/toc.xhtml", nav_doc),
+            ("epub/chapter-1.xhtml", CHAPTER),
+            ("epub/font.ttf", ""),
+            ("epub/stray.css", ""),
+        ] {
+            writer.start_file(path, FileOptions::default()).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let report = Parser::from_bytes(bytes).unwrap().validate();
+        assert!(!report.is_valid());
+        assert_eq!(
+            vec![
+                Code::MimetypeNotFirst,
+                Code::MimetypeCompressed,
+                Code::MimetypeContent,
+                Code::MissingLanguage,
+                Code::MissingModified,
+                Code::ResourceMissing,
+                Code::UnknownMediaType,
+                Code::IdrefNotFound,
+                Code::TocHrefNotInManifest,
+                Code::ResourceUndeclared,
+            ],
+            report
+                .items
+                .iter()
+                .map(|item| item.code)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "error[resource-missing] epub/content.opf:9:9: `epub/chapter-2.xhtml` not found",
+            report.items[5].to_string()
+        );
+        assert_eq!(
+            vec!["epub/stray.css"],
+            report
+                .warnings()
+                .filter(|item| item.code == Code::ResourceUndeclared)
+                .map(|item| item.location.path.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn validate_unreadable_package() {
+        let codes = |bytes: Vec<u8>| {
+            let report = Parser::from_bytes(bytes).unwrap().validate();
+            report
+                .items
+                .iter()
+                .map(|item| item.code)
+                .collect::<Vec<_>>()
+        };
+        let no_root_file = r#"<container xmlns="urn:oasis:names:tc:opendocument:xmlns:container" version="1.0">
+    <rootfiles/>
+</container>"#;
+
+        assert_eq!(
+            vec![Code::ContainerMissing],
+            codes(build_zip(&[("mimetype", b"application/epub+zip")]))
+        );
+        assert_eq!(
+            vec![Code::NoRootFile],
+            codes(build_zip(&[
+                ("mimetype", b"application/epub+zip"),
+                ("META-INF/container.xml", no_root_file.as_bytes()),
+            ]))
+        );
+        assert_eq!(
+            vec![Code::PackageDocumentMissing],
+            codes(build_zip(&[
+                ("mimetype", b"application/epub+zip"),
+                ("META-INF/container.xml", CONTAINER.as_bytes()),
+            ]))
+        );
+        assert_eq!(
+            vec![Code::MalformedXml],
+            codes(epub("<package><metadata></package>", &[]))
+        );
+    }
+
+    #[test]
+    fn validate_built_book() {
+        let metadata = Metadata {
+            titles: vec![Title {
+                value: String::from("Sample Book"),
+                ..Default::default()
+            }],
+            languages: vec![String::from("en")],
+            ..Default::default()
+        };
+        let spine = Spine {
+            items: vec![SpineItem {
+                idref: String::from("chapter-1"),
+                linear: true,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let bytes = EpubBuilder::new(metadata)
+            .resource(
+                ManifestItem {
+                    id: String::from("chapter-1"),
+                    href: String::from("chapter-1.xhtml"),
+                    ..Default::default()
+                },
+                CHAPTER,
+            )
+            .spine(spine)
+            .ncx(true)
+            .to_bytes()
+            .unwrap();
+
+        let report = Parser::from_bytes(bytes).unwrap().validate();
+        assert_eq!(Vec::<ReportItem>::new(), report.items);
+    }
+}
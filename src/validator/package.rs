@@ -0,0 +1,356 @@
+use std::collections::HashSet;
+use std::io::{Read, Seek};
+
+use roxmltree::Node;
+use zip::ZipArchive;
+
+use crate::error::Error;
+use crate::parser::{Href, Manifest, PackageDocument, RootFile, Toc, TocNode};
+use crate::util::path;
+use crate::util::xml_util;
+use crate::util::zip_util::read_text_file;
+use crate::validator::{Code, Location, Report};
+
+/// EPUB 3 core media types, usable without a fallback
+const CORE_MEDIA_TYPES: [&str; 22] = [
+    "application/xhtml+xml",
+    "image/svg+xml",
+    "text/css",
+    "text/javascript",
+    "application/javascript",
+    "application/ecmascript",
+    "application/x-dtbncx+xml",
+    "application/smil+xml",
+    "application/pls+xml",
+    "image/gif",
+    "image/jpeg",
+    "image/png",
+    "image/webp",
+    "font/ttf",
+    "font/otf",
+    "font/woff",
+    "font/woff2",
+    "application/font-sfnt",
+    "application/vnd.ms-opentype",
+    "audio/mpeg",
+    "audio/mp4",
+    "audio/opus",
+];
+
+/// media types a spine item, or one of its fallbacks, must have
+const CONTENT_DOCUMENT_MEDIA_TYPES: [&str; 2] = ["application/xhtml+xml", "image/svg+xml"];
+
+/// Check the package document of `root_file` and its tables of contents, adding its resources
+/// to `declared`. Returns `false` when the package document could not be parsed.
+pub(super) fn check<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    entries: &HashSet<String>,
+    root_file: &RootFile,
+    declared: &mut HashSet<String>,
+    report: &mut Report,
+) -> bool {
+    let path = root_file.full_path.as_str();
+    let source = match read_text_file(archive, path) {
+        Ok(source) => source,
+        Err(Error::MissingResource { path }) => {
+            report.push_error(Error::MissingPackageDocument { path }, &root_file.full_path);
+            return false;
+        }
+        Err(err) => {
+            report.push_error(err, path);
+            return false;
+        }
+    };
+    let doc = match xml_util::parse(&source, path) {
+        Ok(doc) => doc,
+        Err(err) => {
+            report.push_error(err, path);
+            return false;
+        }
+    };
+    let pkg_doc = match PackageDocument::from(&source, root_file) {
+        Ok(pkg_doc) => pkg_doc,
+        Err(err) => {
+            report.push_error(err, path);
+            return false;
+        }
+    };
+
+    let package_elem = doc.root_element();
+    let child = |name: &str| package_elem.children().find(|node| node.has_tag_name(name));
+    // EPUB 2 packages always declare their version
+    let epub3 = !package_elem
+        .attribute("version")
+        .is_some_and(|version| version.trim().starts_with('2'));
+
+    if let Some(metadata_elem) = child("metadata") {
+        check_metadata(&pkg_doc, &package_elem, &metadata_elem, epub3, path, report);
+    }
+    if let Some(manifest_elem) = child("manifest") {
+        check_manifest(&pkg_doc.manifest, &manifest_elem, entries, path, report);
+    }
+    if let Some(spine_elem) = child("spine") {
+        check_spine(&pkg_doc.manifest, &spine_elem, path, report);
+    }
+    declared.extend(pkg_doc.manifest.iter().map(|item| item.path.clone()));
+
+    if epub3 && pkg_doc.toc_nav_doc_path.is_none() {
+        report.push(
+            Code::MissingNavDocument,
+            Location::new(path),
+            String::from("no manifest item with the `nav` property"),
+        );
+    }
+    let tocs = [
+        (
+            &pkg_doc.toc_nav_doc_path,
+            Toc::from_nav_doc as fn(&str, &str) -> _,
+        ),
+        (&pkg_doc.toc_ncx_path, Toc::from_ncx),
+    ];
+    for (toc_path, parse) in tocs {
+        let Some(toc_path) = toc_path else {
+            continue;
+        };
+        // a missing document is already reported with the manifest
+        if !entries.contains(toc_path) {
+            continue;
+        }
+        match read_text_file(archive, toc_path).and_then(|doc| parse(&doc, toc_path)) {
+            Ok(toc) => check_toc(&toc, &pkg_doc.manifest, toc_path, report),
+            Err(err) => report.push_error(err, toc_path),
+        }
+    }
+
+    true
+}
+
+fn check_metadata(
+    pkg_doc: &PackageDocument,
+    package_elem: &Node,
+    metadata_elem: &Node,
+    epub3: bool,
+    path: &str,
+    report: &mut Report,
+) {
+    let metadata = &pkg_doc.metadata;
+    let location = Location::at(path, metadata_elem);
+
+    if metadata.identifiers.is_empty() {
+        report.push(
+            Code::MissingIdentifier,
+            location.clone(),
+            String::from("no `dc:identifier`"),
+        );
+    } else if metadata.unique_identifier.is_none() {
+        let message = match package_elem.attribute("unique-identifier") {
+            Some(id) => format!("no `dc:identifier` with the unique identifier id `{}`", id),
+            None => String::from("`package` has no `unique-identifier`"),
+        };
+        report.push(
+            Code::UniqueIdentifierNotFound,
+            Location::at(path, package_elem),
+            message,
+        );
+    }
+    if metadata
+        .titles
+        .iter()
+        .all(|title| title.value.trim().is_empty())
+    {
+        report.push(
+            Code::MissingTitle,
+            location.clone(),
+            String::from("no `dc:title`"),
+        );
+    }
+    if metadata.languages.is_empty() {
+        report.push(
+            Code::MissingLanguage,
+            location.clone(),
+            String::from("no `dc:language`"),
+        );
+    }
+    if epub3 && metadata.modified.is_none() {
+        report.push(
+            Code::MissingModified,
+            location,
+            String::from("no `meta property=\"dcterms:modified\"`"),
+        );
+    }
+}
+
+fn check_manifest(
+    manifest: &Manifest,
+    manifest_elem: &Node,
+    entries: &HashSet<String>,
+    path: &str,
+    report: &mut Report,
+) {
+    let mut ids = HashSet::new();
+    for node in manifest_elem
+        .children()
+        .filter(|node| node.has_tag_name("item"))
+    {
+        let location = Location::at(path, &node);
+        for attribute in ["id", "href"] {
+            if node.attribute(attribute).is_none() {
+                report.push(
+                    Code::MissingAttribute,
+                    location.clone(),
+                    format!("manifest item without `{}`", attribute),
+                );
+            }
+        }
+        let Some(id) = node.attribute("id") else {
+            continue;
+        };
+        if !ids.insert(id) {
+            report.push(
+                Code::DuplicateId,
+                location,
+                format!("duplicate manifest item id `{}`", id),
+            );
+            continue;
+        }
+        let Some(item) = manifest.by_id(id) else {
+            continue;
+        };
+
+        if !path::is_remote(&item.path) && !entries.contains(&item.path) {
+            report.push(
+                Code::ResourceMissing,
+                location.clone(),
+                format!("`{}` not found", item.path),
+            );
+        }
+        match &item.media_type {
+            None => report.push(
+                Code::MissingMediaType,
+                location.clone(),
+                format!("`{}` has no media type", item.href),
+            ),
+            Some(media_type) if item.fallback.is_none() && !is_core_media_type(media_type) => {
+                report.push(
+                    Code::UnknownMediaType,
+                    location.clone(),
+                    format!(
+                        "`{}` is not a core media type and `{}` has no fallback",
+                        media_type, item.href
+                    ),
+                )
+            }
+            Some(_) => {}
+        }
+        for (attribute, idref) in [
+            ("fallback", &item.fallback),
+            ("media-overlay", &item.media_overlay),
+        ] {
+            if let Some(idref) = idref.as_deref() {
+                if manifest.by_id(idref).is_none() {
+                    report.push(
+                        Code::IdrefNotFound,
+                        location.clone(),
+                        format!("`{}` references the unknown item `{}`", attribute, idref),
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn check_spine(manifest: &Manifest, spine_elem: &Node, path: &str, report: &mut Report) {
+    if let Some(toc) = spine_elem.attribute("toc") {
+        if manifest.by_id(toc).is_none() {
+            report.push(
+                Code::IdrefNotFound,
+                Location::at(path, spine_elem),
+                format!("`toc` references the unknown item `{}`", toc),
+            );
+        }
+    }
+
+    let itemrefs: Vec<Node> = spine_elem
+        .children()
+        .filter(|node| node.has_tag_name("itemref"))
+        .collect();
+    if itemrefs.is_empty() {
+        report.push(
+            Code::SpineEmpty,
+            Location::at(path, spine_elem),
+            String::from("the spine has no `itemref`"),
+        );
+    }
+    for node in itemrefs {
+        let location = Location::at(path, &node);
+        let Some(idref) = node.attribute("idref") else {
+            report.push(
+                Code::MissingAttribute,
+                location,
+                String::from("itemref without `idref`"),
+            );
+            continue;
+        };
+        let chain = manifest.fallback_chain(idref);
+        let Some(item) = chain.first() else {
+            report.push(
+                Code::IdrefNotFound,
+                location,
+                format!("`idref` references the unknown item `{}`", idref),
+            );
+            continue;
+        };
+        let is_content_document = chain.iter().any(|item| {
+            item.media_type
+                .as_deref()
+                .is_some_and(|media_type| CONTENT_DOCUMENT_MEDIA_TYPES.contains(&media_type))
+        });
+        if !is_content_document {
+            report.push(
+                Code::NonContentSpineItem,
+                location,
+                format!(
+                    "`{}` is not an XHTML or SVG content document and has no fallback to one",
+                    item.href
+                ),
+            );
+        }
+    }
+}
+
+fn check_toc(toc: &Toc, manifest: &Manifest, path: &str, report: &mut Report) {
+    let mut hrefs = Vec::new();
+    collect_hrefs(&toc.contents, &mut hrefs);
+    for nav_list in &toc.nav_lists {
+        collect_hrefs(&nav_list.targets, &mut hrefs);
+    }
+    hrefs.extend(toc.landmarks.iter().map(|landmark| &landmark.href));
+    hrefs.extend(toc.page_list.iter().map(|page| &page.href));
+
+    for href in hrefs {
+        if !path::is_remote(&href.path) && manifest.by_path(&href.path).is_none() {
+            report.push(
+                Code::TocHrefNotInManifest,
+                Location::new(path),
+                format!("`{}` links to a resource outside the manifest", href),
+            );
+        }
+    }
+}
+
+fn collect_hrefs<'a>(nodes: &'a [TocNode], hrefs: &mut Vec<&'a Href>) {
+    for node in nodes {
+        hrefs.extend(&node.href);
+        if let Some(children) = &node.children {
+            collect_hrefs(children, hrefs);
+        }
+    }
+}
+
+fn is_core_media_type(media_type: &str) -> bool {
+    // parameters, e.g. `audio/mp4; codecs="mp4a.40.2"`, don't change the type
+    let media_type = media_type.split(';').next().unwrap_or_default().trim();
+    CORE_MEDIA_TYPES
+        .iter()
+        .any(|core| core.eq_ignore_ascii_case(media_type))
+}